@@ -1,9 +1,16 @@
 use std::{collections::HashMap, fmt::Display};
 
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::methods::{Method, MethodType};
+use crate::{
+    calls::INFLUX_ORG_ID_HEADER_NAME,
+    decoder::MethodRegistry,
+    entry::Logger,
+    methods::{format_id, Method, MethodType},
+    status::StatusDetails,
+};
 
 /// Represents a logical gRPC call extracted from a chain of Entrys
 ///
@@ -12,27 +19,40 @@ use crate::methods::{Method, MethodType};
 pub struct Call {
     pub id: u64,
 
+    /// Which side of the call it was logged by
+    pub logged_by: Logger,
+
     /// gRPR method name
     pub method_name: Option<String>,
 
     /// decoded gRPC request message (depends on method_name)
     pub request: Option<Method>,
 
+    /// gRPC request message exactly as it was logged (unless it was
+    /// truncated), e.g. to replay it
+    pub request_data: Option<Bytes>,
+
     /// decoded gRPC response messages (depends on method_name)
     /// Note: since a conversation can contain multiple messages (e.g., multiple
     /// Server messages) there can be multiple responses for a single `Call`.
     pub responses: Vec<Method>,
 
+    /// gRPC response messages exactly as they were logged, one per
+    /// response, `None` for those that were truncated, e.g. to serve
+    /// them again
+    pub response_data: Vec<Option<Bytes>>,
+
     /// first observed timestamp of this call
     pub start_time: Option<DateTime<Utc>>,
 
     /// last observed timestamp of this call
     pub end_time: Option<DateTime<Utc>>,
 
-    /// Other end of the request
+    /// Other end of the request: the server when logged by the client,
+    /// and the client when logged by the server
     pub peer: Option<String>,
 
-    /// authority (client dns name)
+    /// authority (the server name the client sent the request to)
     pub authority: Option<String>,
 
     /// Headers sent from Client
@@ -47,11 +67,196 @@ pub struct Call {
     /// Response message
     pub status_message: Option<String>,
 
-    /// Response details (decoded as string)
-    pub status_details: Option<String>,
+    /// Response details (usually a `google.rpc.Status`)
+    pub status_details: Option<StatusDetails>,
 
     /// Trailer metadata
     pub status_metadata: HashMap<String, String>,
+
+    /// What happened to the call, and when
+    pub lifecycle: Lifecycle,
+
+    /// Unexpected things seen while reconstructing this call
+    pub anomalies: Vec<Anomaly>,
+
+    /// sequence id of the entry currently being added, if known
+    #[serde(skip)]
+    sequence_id: Option<u64>,
+}
+
+/// A step in the life of a call
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum CallState {
+    /// The client sent its headers (including the method name)
+    ClientHeaders,
+    /// The client sent a request message
+    Request,
+    /// The client is done sending messages
+    HalfClosed,
+    /// The server sent its headers
+    ServerHeaders,
+    /// The server sent a response message
+    Response,
+    /// The server sent the trailer (status), ending the call
+    Trailer,
+    /// The call was cancelled (typically by the client)
+    Cancelled,
+}
+
+impl Display for CallState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::ClientHeaders => "client headers",
+            Self::Request => "request",
+            Self::HalfClosed => "half closed",
+            Self::ServerHeaders => "server headers",
+            Self::Response => "response",
+            Self::Trailer => "trailer",
+            Self::Cancelled => "cancelled",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How a call ended, as far as the capture shows
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Outcome {
+    /// The server sent the trailer
+    Completed,
+    /// The call was cancelled before the server sent the trailer
+    Cancelled,
+    /// The capture ended (or entries were lost) before the call did
+    Incomplete,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Transition {
+    pub state: CallState,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// The states a call went through, in the order they were logged
+#[derive(Clone, Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct Lifecycle {
+    pub transitions: Vec<Transition>,
+}
+
+impl Lifecycle {
+    pub fn push(&mut self, state: CallState, timestamp: Option<DateTime<Utc>>) {
+        self.transitions.push(Transition { state, timestamp });
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        let ended = self.transitions.iter().find_map(|t| match t.state {
+            CallState::Trailer => Some(Outcome::Completed),
+            CallState::Cancelled => Some(Outcome::Cancelled),
+            _ => None,
+        });
+        ended.unwrap_or(Outcome::Incomplete)
+    }
+}
+
+impl Display for Lifecycle {
+    /// e.g. `client headers +0.000ms -> request +0.012ms -> 3 x response +1.200ms..+4.500ms`,
+    /// where times are relative to the first transition
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let origin = self.transitions.iter().find_map(|t| t.timestamp);
+        let offset = |t: &Transition| match (t.timestamp, origin) {
+            (Some(ts), Some(origin)) => (ts - origin)
+                .num_microseconds()
+                .map(|micros| format!("+{:.3}ms", micros as f64 / 1000.0))
+                .unwrap_or_else(|| "??".to_string()),
+            _ => "??".to_string(),
+        };
+
+        let mut first = true;
+        let mut transitions = self.transitions.iter().peekable();
+        while let Some(start) = transitions.next() {
+            // collapse runs of the same state (e.g. many responses)
+            let mut count = 1;
+            let mut end = start;
+            while let Some(next) = transitions.next_if(|t| t.state == start.state) {
+                count += 1;
+                end = next;
+            }
+
+            if !first {
+                write!(f, " -> ")?;
+            }
+            first = false;
+
+            if count == 1 {
+                write!(f, "{} {}", start.state, offset(start))?;
+            } else {
+                write!(
+                    f,
+                    "{} x {} {}..{}",
+                    count,
+                    start.state,
+                    offset(start),
+                    offset(end)
+                )?;
+            }
+        }
+
+        if self.outcome() == Outcome::Incomplete {
+            if !first {
+                write!(f, " -> ")?;
+            }
+            write!(f, "<incomplete>")?;
+        }
+        Ok(())
+    }
+}
+
+/// Something unexpected in the log entries of a call. The call is still
+/// reconstructed as well as possible, but may be incomplete.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+
+    /// sequence id (within the call) of the offending entry, if known
+    pub sequence_id: Option<u64>,
+
+    pub detail: String,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AnomalyKind {
+    /// The header or trailer metadata was truncated by the logger
+    TruncatedPayload,
+    /// Logged by neither the client nor the server
+    UnexpectedLogger,
+    /// A message payload on an event that is not a message
+    UnexpectedMessageEvent,
+    /// The declared message length differs from the logged data
+    MismatchedMessageLength,
+    /// A message was seen before the method name, so can't be decoded
+    MessageBeforeHeader,
+    /// An entry was logged after the call was cancelled
+    EntryAfterCancel,
+    DifferentPeer,
+    DuplicateMethodName,
+    DuplicateAuthority,
+    DuplicateRequest,
+    DuplicateClientHeader,
+    DuplicateServerHeader,
+    DuplicateStatusMetadata,
+    DuplicateStatusCode,
+    DuplicateStatusMessage,
+    DuplicateStatusDetails,
+    /// Status details were not a `google.rpc.Status`
+    InvalidStatusDetails,
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.kind)?;
+        if let Some(sequence_id) = self.sequence_id {
+            write!(f, " (entry {})", sequence_id)?;
+        }
+        write!(f, ": {}", self.detail)
+    }
 }
 
 impl Display for Call {
@@ -76,12 +281,12 @@ impl Display for Call {
             write!(f, " {}", method_name)?;
         }
 
-        write!(
-            f,
-            " {} --> {}",
-            self.authority.as_deref().unwrap_or("<UNKNOWN>"),
-            self.peer.as_deref().unwrap_or("<UNKNOWN>")
-        )?;
+        let authority = self.authority.as_deref().unwrap_or("<UNKNOWN>");
+        let peer = self.peer.as_deref().unwrap_or("<UNKNOWN>");
+        match self.logged_by {
+            Logger::Server => write!(f, " {} --> {} (server side)", peer, authority)?,
+            _ => write!(f, " {} --> {}", authority, peer)?,
+        }
 
         Ok(())
     }
@@ -95,6 +300,18 @@ impl Call {
         }
     }
 
+    /// The org the call was made for: the `influx-org-id` header, or
+    /// if that is missing, the org of the request's `ReadSource`
+    pub fn org_id(&self) -> Option<String> {
+        self.client_headers
+            .get(INFLUX_ORG_ID_HEADER_NAME)
+            .cloned()
+            .or_else(|| {
+                let read_source = self.request.as_ref()?.read_source()?;
+                Some(format_id(read_source.org_id))
+            })
+    }
+
     /// Note that a timestamp occured as part of this call
     pub fn with_timestamp(&mut self, timestamp: Option<DateTime<Utc>>) -> &mut Self {
         if let Some(timestamp) = timestamp {
@@ -113,135 +330,286 @@ impl Call {
         self
     }
 
+    /// Note that the call reached `state` at `timestamp`
+    pub fn with_transition(
+        &mut self,
+        state: CallState,
+        timestamp: Option<DateTime<Utc>>,
+    ) -> &mut Self {
+        self.lifecycle.push(state, timestamp);
+        self
+    }
+
+    /// Note the sequence id of the entry that is being added, so that
+    /// any anomalies it causes can refer to it
+    pub fn with_sequence_id(&mut self, sequence_id: u64) -> &mut Self {
+        self.sequence_id = Some(sequence_id);
+        self
+    }
+
+    /// Records something unexpected about the entry being added
+    pub fn with_anomaly(&mut self, kind: AnomalyKind, detail: impl Into<String>) -> &mut Self {
+        self.anomalies.push(Anomaly {
+            kind,
+            sequence_id: self.sequence_id,
+            detail: detail.into(),
+        });
+        self
+    }
+
+    pub fn with_logged_by(&mut self, logged_by: Logger) -> &mut Self {
+        self.logged_by = logged_by;
+        self
+    }
+
     pub fn with_peer(&mut self, peer: Option<String>) -> &mut Self {
-        if let Some(peer) = peer {
-            self.peer = self
-                .peer
-                .take()
-                .map(|existing_peer| {
-                    assert_eq!(existing_peer, peer, "Unexpectedly different peer in log");
-                    existing_peer
-                })
-                .or(Some(peer));
+        match (&self.peer, peer) {
+            (_, None) => {}
+            (None, Some(peer)) => self.peer = Some(peer),
+            (Some(existing_peer), Some(peer)) => {
+                if *existing_peer != peer {
+                    let detail = format!("{} and {}", existing_peer, peer);
+                    self.with_anomaly(AnomalyKind::DifferentPeer, detail);
+                }
+            }
         }
 
         self
     }
 
     pub fn with_method_name(&mut self, method_name: String) -> &mut Self {
-        assert!(
-            self.method_name.is_none(),
-            "Already have method name: {:?}",
-            self.method_name
-        );
+        if let Some(existing) = &self.method_name {
+            let detail = format!("already have {}, ignoring {}", existing, method_name);
+            return self.with_anomaly(AnomalyKind::DuplicateMethodName, detail);
+        }
         self.method_name = Some(method_name);
         self
     }
 
-    pub fn with_request_data(&mut self, method_data: Vec<u8>) -> &mut Self {
-        assert!(
-            self.request.is_none(),
-            "Already have request: {:?}",
-            self.request
-        );
+    pub fn with_request_data(
+        &mut self,
+        method_data: Vec<u8>,
+        decoders: &MethodRegistry,
+    ) -> &mut Self {
+        if let Some(existing) = &self.request {
+            let detail = format!("already have {:?}", existing);
+            return self.with_anomaly(AnomalyKind::DuplicateRequest, detail);
+        }
 
-        let method = if let Some(method_name) = &self.method_name {
-            Method::new(method_name, method_data, MethodType::Request)
+        if let Some(method_name) = &self.method_name {
+            self.request_data = Some(method_data.clone().into());
+            let method = decoders.decode(method_name, method_data, MethodType::Request);
+            self.request = Some(method);
         } else {
             // could be smarter here and postpone decoding if method_name hasn't been seen yet
-            panic!("Got method data before method_name, so don't know how to decode it");
-        };
-
-        self.request = Some(method);
+            let detail = format!("dropped {} byte request", method_data.len());
+            self.with_anomaly(AnomalyKind::MessageBeforeHeader, detail);
+        }
         self
     }
 
-    pub fn with_response_data(&mut self, method_data: Vec<u8>) -> &mut Self {
-        let method = if let Some(method_name) = &self.method_name {
-            Method::new(method_name, method_data, MethodType::Response)
+    pub fn with_response_data(
+        &mut self,
+        method_data: Vec<u8>,
+        decoders: &MethodRegistry,
+    ) -> &mut Self {
+        if let Some(method_name) = &self.method_name {
+            self.response_data.push(Some(method_data.clone().into()));
+            let method = decoders.decode(method_name, method_data, MethodType::Response);
+            self.responses.push(method);
         } else {
             // could be smarter here and postpone decoding if method_name hasn't been seen yet
-            panic!("Got method data before method_name, so don't know how to decode it");
-        };
+            let detail = format!("dropped {} byte response", method_data.len());
+            self.with_anomaly(AnomalyKind::MessageBeforeHeader, detail);
+        }
+        self
+    }
+
+    /// Note a request of which only the first part was captured
+    pub fn with_truncated_request_data(
+        &mut self,
+        length: u32,
+        method_data: Vec<u8>,
+        decoders: &MethodRegistry,
+    ) -> &mut Self {
+        if let Some(existing) = &self.request {
+            let detail = format!("already have {:?}", existing);
+            return self.with_anomaly(AnomalyKind::DuplicateRequest, detail);
+        }
+
+        if let Some(method_name) = &self.method_name {
+            let method =
+                decoders.decode_truncated(method_name, length, method_data, MethodType::Request);
+            self.request = Some(method);
+        } else {
+            let detail = format!("dropped truncated {} byte request", length);
+            self.with_anomaly(AnomalyKind::MessageBeforeHeader, detail);
+        }
+        self
+    }
 
-        self.responses.push(method);
+    /// Note a response of which only the first part was captured
+    pub fn with_truncated_response_data(
+        &mut self,
+        length: u32,
+        method_data: Vec<u8>,
+        decoders: &MethodRegistry,
+    ) -> &mut Self {
+        if let Some(method_name) = &self.method_name {
+            let method =
+                decoders.decode_truncated(method_name, length, method_data, MethodType::Response);
+            self.response_data.push(None);
+            self.responses.push(method);
+        } else {
+            let detail = format!("dropped truncated {} byte response", length);
+            self.with_anomaly(AnomalyKind::MessageBeforeHeader, detail);
+        }
         self
     }
 
+    /// Decodes the request and responses that were kept as
+    /// [`Method::Unknown`], if `decoders` knows their method
+    pub fn decode_unknown(&mut self, decoders: &MethodRegistry) {
+        if let Some(request) = self.request.as_mut() {
+            decode_unknown(request, MethodType::Request, decoders);
+        }
+        for response in &mut self.responses {
+            decode_unknown(response, MethodType::Response, decoders);
+        }
+    }
+
     pub fn with_authority(&mut self, authority: String) -> &mut Self {
-        assert!(
-            self.authority.is_none(),
-            "Already have authority name: {:?}",
-            self.authority
-        );
+        if let Some(existing) = &self.authority {
+            let detail = format!("already have {}, ignoring {}", existing, authority);
+            return self.with_anomaly(AnomalyKind::DuplicateAuthority, detail);
+        }
         self.authority = Some(authority);
         self
     }
 
     pub fn with_client_headers(&mut self, headers: HashMap<String, String>) -> &mut Self {
         for (name, value) in headers {
-            let existing_header = self.client_headers.insert(name, value);
-            assert!(
-                existing_header.is_none(),
-                "duplicated client header: {:?}",
-                existing_header
-            );
+            if let Some(existing) = self.client_headers.insert(name.clone(), value) {
+                let detail = format!("{} (was {})", name, existing);
+                self.with_anomaly(AnomalyKind::DuplicateClientHeader, detail);
+            }
         }
         self
     }
 
     pub fn with_server_headers(&mut self, headers: HashMap<String, String>) -> &mut Self {
         for (name, value) in headers {
-            let existing_header = self.server_headers.insert(name, value);
-            assert!(
-                existing_header.is_none(),
-                "duplicated server header: {:?}",
-                existing_header
-            );
+            if let Some(existing) = self.server_headers.insert(name.clone(), value) {
+                let detail = format!("{} (was {})", name, existing);
+                self.with_anomaly(AnomalyKind::DuplicateServerHeader, detail);
+            }
         }
         self
     }
 
     pub fn with_status_metadata(&mut self, metadata: HashMap<String, String>) -> &mut Self {
         for (name, value) in metadata {
-            let existing_header = self.status_metadata.insert(name, value);
-            assert!(
-                existing_header.is_none(),
-                "duplicated status metadata header: {:?}",
-                existing_header
-            );
+            if let Some(existing) = self.status_metadata.insert(name.clone(), value) {
+                let detail = format!("{} (was {})", name, existing);
+                self.with_anomaly(AnomalyKind::DuplicateStatusMetadata, detail);
+            }
         }
         self
     }
 
     pub fn with_status_code(&mut self, status_code: u32) -> &mut Self {
-        assert!(
-            self.status_code.is_none(),
-            "Already have status code: {:?}",
-            self.status_code
-        );
+        if let Some(existing) = self.status_code {
+            let detail = format!("already have {}, ignoring {}", existing, status_code);
+            return self.with_anomaly(AnomalyKind::DuplicateStatusCode, detail);
+        }
         self.status_code = Some(status_code);
         self
     }
 
     pub fn with_status_message(&mut self, status_message: String) -> &mut Self {
-        assert!(
-            self.status_message.is_none(),
-            "Already have status message: {:?}",
-            self.status_message
-        );
+        if let Some(existing) = &self.status_message {
+            let detail = format!("already have {:?}, ignoring {:?}", existing, status_message);
+            return self.with_anomaly(AnomalyKind::DuplicateStatusMessage, detail);
+        }
         self.status_message = Some(status_message);
         self
     }
 
-    pub fn with_status_details(&mut self, status_details: impl Into<String>) -> &mut Self {
-        let status_details = status_details.into();
-        assert!(
-            self.status_details.is_none(),
-            "Already have status details: {:?}",
-            self.status_details
-        );
+    /// Decodes the serialized `google.rpc.Status` in `status_details`,
+    /// if any were sent. Expects the status code to have been added first.
+    pub fn with_status_details(&mut self, status_details: Vec<u8>) -> &mut Self {
+        if status_details.is_empty() {
+            return self;
+        }
+
+        let status_details = StatusDetails::decode(status_details, self.status_code);
+        if let Some(existing) = &self.status_details {
+            let detail = format!("already have {}, ignoring {}", existing, status_details);
+            return self.with_anomaly(AnomalyKind::DuplicateStatusDetails, detail);
+        }
+        if let StatusDetails::Raw { error, .. } = &status_details {
+            let detail = error.clone();
+            self.with_anomaly(AnomalyKind::InvalidStatusDetails, detail);
+        }
         self.status_details = Some(status_details);
         self
     }
 }
+
+fn decode_unknown(method: &mut Method, method_type: MethodType, decoders: &MethodRegistry) {
+    if let Method::Unknown { method_name, bytes } = method {
+        if decoders.knows(method_name) {
+            *method = decoders.decode(method_name, bytes.to_vec(), method_type);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use generated_types::{
+        google::protobuf::Any,
+        influxdata::platform::storage::{ReadFilterRequest, ReadSource},
+    };
+    use prost::Message;
+
+    use super::*;
+    use crate::methods::READ_SOURCE_TYPE_URL;
+
+    fn call(org_header: Option<&str>, source_org_id: Option<u64>) -> Call {
+        let mut call = Call::new(1);
+        if let Some(org_id) = org_header {
+            call.with_client_headers(HashMap::from([(
+                INFLUX_ORG_ID_HEADER_NAME.to_string(),
+                org_id.to_string(),
+            )]));
+        }
+        let read_source = source_org_id.map(|org_id| Any {
+            type_url: READ_SOURCE_TYPE_URL.to_string(),
+            value: ReadSource {
+                org_id,
+                bucket_id: 2,
+                partition_id: 0,
+            }
+            .encode_to_vec()
+            .into(),
+        });
+        call.request = Some(Method::ReadFilterRequest(ReadFilterRequest {
+            read_source,
+            ..Default::default()
+        }));
+        call
+    }
+
+    #[test]
+    fn org_id_from_header_or_read_source() {
+        assert_eq!(
+            call(Some("000000000000abcd"), Some(1)).org_id(),
+            Some("000000000000abcd".to_string())
+        );
+        assert_eq!(
+            call(None, Some(0xabcd)).org_id(),
+            Some("000000000000abcd".to_string())
+        );
+        assert_eq!(call(None, None).org_id(), None);
+    }
+}
@@ -1,19 +1,26 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    call::Call,
+    call::{AnomalyKind, Call, CallState, Outcome},
+    decoder::MethodRegistry,
     entry::{ClientHeader, Entry, EventType, Logger, Message, Payload, ServerHeader, Trailer},
+    filter::Filter,
+    methods::{format_id, Method},
 };
 
 // gRPC header key used to identify source org ID for conversation
-const INFLUX_ORG_ID_HEADER_NAME: &str = "influx-org-id";
+pub const INFLUX_ORG_ID_HEADER_NAME: &str = "influx-org-id";
+
+// how many cancelled calls are held back, in case more of their entries
+// (e.g. the trailer, or a response that was already on its way) follow
+const MAX_HELD_CANCELLED_CALLS: usize = 1000;
 
 /// Group `Entries` into logical gRPC calls
 ///
 /// To use:
-/// ```
+/// ```ignore
 /// let calls: Calls = Entries::try_from(file)?;
 /// for call in calls {
 ///   // do awesome stuff
@@ -30,6 +37,10 @@ impl Calls {
         self.calls.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &Call> {
         self.calls.iter()
     }
@@ -39,6 +50,58 @@ impl Calls {
         self.calls.extend(other.calls.into_iter());
     }
 
+    /// Number of anomalies of each kind across all calls
+    pub fn anomaly_summary(&self) -> BTreeMap<AnomalyKind, usize> {
+        let mut summary = BTreeMap::new();
+        for anomaly in self.calls.iter().flat_map(|call| call.anomalies.iter()) {
+            *summary.entry(anomaly.kind).or_default() += 1;
+        }
+        summary
+    }
+
+    /// Number of calls that ended each way
+    pub fn outcome_summary(&self) -> BTreeMap<Outcome, usize> {
+        let mut summary = BTreeMap::new();
+        for call in self.calls.iter() {
+            *summary.entry(call.lifecycle.outcome()).or_default() += 1;
+        }
+        summary
+    }
+
+    /// Number of requests and responses of each method that were kept as
+    /// [`Method::Unknown`], as there was no decoder for the method
+    pub fn unknown_summary(&self) -> BTreeMap<String, usize> {
+        let mut summary = BTreeMap::new();
+        for call in self.calls.iter() {
+            for method in call.request.iter().chain(call.responses.iter()) {
+                if let Method::Unknown { method_name, .. } = method {
+                    *summary.entry(method_name.clone()).or_default() += 1;
+                }
+            }
+        }
+        summary
+    }
+
+    /// Requests and responses that could not be decoded, with their call
+    pub fn decode_failures(&self) -> impl Iterator<Item = (&Call, &Method)> {
+        self.calls.iter().flat_map(|call| {
+            call.request
+                .iter()
+                .chain(call.responses.iter())
+                .filter(|method| method.is_decode_error())
+                .map(move |method| (call, method))
+        })
+    }
+
+    /// Decodes the requests and responses that were kept as
+    /// [`Method::Unknown`] (e.g. in calls written to a binary file
+    /// without `decoders`), if `decoders` knows their method
+    pub fn decode_unknown(&mut self, decoders: &MethodRegistry) {
+        for call in &mut self.calls {
+            call.decode_unknown(decoders);
+        }
+    }
+
     // Filters calls for Offsets from the collection.
     pub fn filter_offset_calls(&mut self) {
         self.calls.retain(|c| {
@@ -51,92 +114,585 @@ impl Calls {
 
     // Filters calls not belonging to the provided org_id from the collection.
     pub fn filter_by_org_id(&mut self, org_id: &str) {
-        self.calls
-            .retain(|c| match c.client_headers.get(INFLUX_ORG_ID_HEADER_NAME) {
-                Some(id) => id.as_str() == org_id,
-                None => false,
-            });
+        self.calls.retain(|c| match c.org_id() {
+            Some(id) => id.as_str() == org_id,
+            None => false,
+        });
+    }
+
+    // Filters calls not for the provided bucket_id from the collection.
+    pub fn filter_by_bucket_id(&mut self, bucket_id: &str) {
+        self.calls.retain(|c| {
+            c.request
+                .as_ref()
+                .and_then(Method::read_source)
+                .map(|read_source| format_id(read_source.bucket_id) == bucket_id)
+                .unwrap_or(false)
+        });
+    }
+
+    // Filters calls not matching the provided filter expression from the collection.
+    pub fn filter_by_expression(&mut self, filter: &Filter) {
+        self.calls.retain(|c| filter.matches(c));
+    }
+}
+
+impl From<Vec<Call>> for Calls {
+    fn from(calls: Vec<Call>) -> Self {
+        Self { calls }
     }
 }
 
 impl<A: Into<Entry>> FromIterator<A> for Calls {
     fn from_iter<T: IntoIterator<Item = A>>(iter: T) -> Self {
-        let builders =
-            iter.into_iter()
-                .fold(BTreeMap::<u64, Call>::new(), |mut builders, entry| {
-                    let entry = entry.into();
-                    //println!("Processing Entry: {:?}", entry);
-                    let Entry {
-                        timestamp,
-                        call_id,
-                        sequence_id_within_call: _,
-                        event_type,
-                        logger,
-                        payload_truncated,
-                        peer,
-                        payload,
-                    } = entry;
-
-                    assert!(!payload_truncated, "truncated payloads not handled yet");
-                    assert!(
-                        matches!(logger, Logger::Client),
-                        "Only handling client logging now"
-                    );
-
-                    let call = builders
-                        .entry(call_id)
-                        .or_insert_with(|| Call::new(call_id))
-                        .with_timestamp(timestamp)
-                        .with_peer(peer);
-
-                    match payload {
-                        Payload::ClientHeader(client_header) => {
-                            let ClientHeader {
-                                metadata,
-                                method_name,
-                                authority,
-                                timeout: _,
-                            } = client_header;
-                            call.with_method_name(method_name)
-                                .with_authority(authority)
-                                .with_client_headers(metadata)
-                        }
-                        Payload::ServerHeader(server_header) => {
-                            let ServerHeader { metadata } = server_header;
-
-                            call.with_server_headers(metadata)
-                        }
-                        Payload::Message(message) => {
-                            let Message { length, data } = message;
-                            assert_eq!(length as usize, data.len(), "mismatched data length");
-                            match event_type {
-                                EventType::ClientMessage => call.with_request_data(data),
-                                EventType::ServerMessage => call.with_response_data(data),
-                                _ => panic!("Unexpected payload in event type {:?}", event_type),
-                            }
-                        }
-                        Payload::Trailer(trailer) => {
-                            let Trailer {
-                                metadata,
-                                status_code,
-                                status_message,
-                                status_details,
-                            } = trailer;
-                            let status_details =
-                                String::from_utf8(status_details).expect("details not string");
-                            call.with_status_metadata(metadata)
-                                .with_status_code(status_code)
-                                .with_status_message(status_message)
-                                .with_status_details(status_details)
-                        }
-                    };
-
-                    //println!("Call after build: {:?}", call);
-                    builders
-                });
-
-        let calls = builders.into_values().collect();
+        let mut builder = CallsBuilder::new();
+        let mut calls: Vec<_> = iter
+            .into_iter()
+            .flat_map(|entry| builder.push(entry))
+            .collect();
+        calls.extend(builder.finish());
+
+        // calls are completed in the order they ended
+        calls.sort_by_key(|call| (call.logged_by, call.id));
 
         Self { calls }
     }
 }
+
+/// Incrementally groups `Entries` into calls, handing back each call
+/// as soon as it ends with a trailer. Cancelled calls are handed back a
+/// little later, so that entries logged after the cancel still end up
+/// in them.
+///
+/// To use:
+/// ```ignore
+/// let mut builder = CallsBuilder::new();
+/// for entry in Entries::try_new(file)? {
+///   for call in builder.push(entry?) {
+///     // do awesome stuff with the complete call
+///   }
+/// }
+/// for call in builder.finish() {
+///   // calls that never completed
+/// }
+/// ```
+#[derive(Default, Debug)]
+pub struct CallsBuilder {
+    /// Calls that have not ended yet, by the side that
+    /// logged them and call id (calls from both sides may be interleaved
+    /// in the same log)
+    in_flight: BTreeMap<(Logger, u64), Call>,
+
+    /// Calls (still in `in_flight`) that were cancelled, oldest first
+    cancelled: VecDeque<(Logger, u64)>,
+
+    /// Used to decode requests and responses
+    decoders: MethodRegistry,
+}
+
+impl CallsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode requests and responses with `decoders`, rather than
+    /// just the storage service's methods
+    pub fn with_decoders(mut self, decoders: MethodRegistry) -> Self {
+        self.decoders = decoders;
+        self
+    }
+
+    /// Number of calls that have not ended yet
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+            .values()
+            .filter(|call| call.lifecycle.outcome() == Outcome::Incomplete)
+            .count()
+    }
+
+    /// Adds `entry` to its call, returning the calls that are complete
+    /// as of `entry`
+    pub fn push(&mut self, entry: impl Into<Entry>) -> Vec<Call> {
+        let entry = entry.into();
+        //println!("Processing Entry: {:?}", entry);
+        let Entry {
+            timestamp,
+            call_id,
+            sequence_id_within_call,
+            event_type,
+            logger,
+            payload_truncated,
+            peer,
+            payload,
+        } = entry;
+
+        let call = self
+            .in_flight
+            .entry((logger, call_id))
+            .or_insert_with(|| {
+                let mut call = Call::new(call_id);
+                call.with_logged_by(logger);
+                call
+            })
+            .with_sequence_id(sequence_id_within_call)
+            .with_timestamp(timestamp)
+            .with_peer(peer);
+
+        let was_cancelled = call.lifecycle.outcome() == Outcome::Cancelled;
+        if was_cancelled {
+            let detail = format!("{:?} after the call was cancelled", event_type);
+            call.with_anomaly(AnomalyKind::EntryAfterCancel, detail);
+        }
+
+        // Entries have the same meaning on both sides (e.g. a
+        // ClientMessage is the request), only the peer differs
+        if !matches!(logger, Logger::Client | Logger::Server) {
+            let detail = format!("{:?} logger", logger);
+            call.with_anomaly(AnomalyKind::UnexpectedLogger, detail);
+        }
+
+        // truncated messages are kept as `Method::Truncated`
+        if payload_truncated && !matches!(payload, Payload::Message(_)) {
+            call.with_anomaly(
+                AnomalyKind::TruncatedPayload,
+                format!("{:?} payload truncated", event_type),
+            );
+        }
+
+        if let Some(state) = call_state(&event_type) {
+            call.with_transition(state, timestamp);
+        }
+
+        let complete = match payload {
+            Payload::ClientHeader(client_header) => {
+                let ClientHeader {
+                    metadata,
+                    method_name,
+                    authority,
+                    timeout: _,
+                } = client_header;
+                call.with_method_name(method_name)
+                    .with_authority(authority)
+                    .with_client_headers(metadata);
+                false
+            }
+            Payload::ServerHeader(server_header) => {
+                let ServerHeader { metadata } = server_header;
+
+                call.with_server_headers(metadata);
+                false
+            }
+            Payload::Message(message) if payload_truncated => {
+                let Message { length, data } = message;
+                match event_type {
+                    EventType::ClientMessage => {
+                        call.with_truncated_request_data(length, data, &self.decoders)
+                    }
+                    EventType::ServerMessage => {
+                        call.with_truncated_response_data(length, data, &self.decoders)
+                    }
+                    _ => call.with_anomaly(
+                        AnomalyKind::UnexpectedMessageEvent,
+                        format!("message payload in event type {:?}", event_type),
+                    ),
+                };
+                false
+            }
+            Payload::Message(message) => {
+                let Message { length, data } = message;
+                if length as usize != data.len() {
+                    let detail = format!("declared {} bytes, got {}", length, data.len());
+                    call.with_anomaly(AnomalyKind::MismatchedMessageLength, detail);
+                }
+                match event_type {
+                    EventType::ClientMessage => call.with_request_data(data, &self.decoders),
+                    EventType::ServerMessage => call.with_response_data(data, &self.decoders),
+                    _ => call.with_anomaly(
+                        AnomalyKind::UnexpectedMessageEvent,
+                        format!("message payload in event type {:?}", event_type),
+                    ),
+                };
+                false
+            }
+            Payload::Trailer(trailer) => {
+                let Trailer {
+                    metadata,
+                    status_code,
+                    status_message,
+                    status_details,
+                } = trailer;
+                call.with_status_metadata(metadata)
+                    .with_status_code(status_code)
+                    .with_status_message(status_message)
+                    .with_status_details(status_details);
+                true
+            }
+            Payload::Empty => false,
+        };
+
+        //println!("Call after build: {:?}", call);
+        let mut completed = vec![];
+        if complete {
+            completed.extend(self.in_flight.remove(&(logger, call_id)));
+        } else if matches!(event_type, EventType::Cancel) && !was_cancelled {
+            self.cancelled.push_back((logger, call_id));
+        }
+
+        // hand back the oldest cancelled calls, unless a trailer already did
+        while self.cancelled.len() > MAX_HELD_CANCELLED_CALLS {
+            let key = self.cancelled.pop_front().expect("not empty");
+            completed.extend(self.in_flight.remove(&key));
+        }
+
+        completed
+    }
+
+    /// Returns the calls that never ended (e.g. because the capture
+    /// ended while they were in flight) and the cancelled calls that
+    /// were still held back, in call id order for each side
+    pub fn finish(self) -> impl Iterator<Item = Call> {
+        self.in_flight.into_values()
+    }
+}
+
+/// The state a call is in after an entry of `event_type`
+fn call_state(event_type: &EventType) -> Option<CallState> {
+    match event_type {
+        EventType::Unknown => None,
+        EventType::ClientHeader => Some(CallState::ClientHeaders),
+        EventType::ServerHeader => Some(CallState::ServerHeaders),
+        EventType::ClientMessage => Some(CallState::Request),
+        EventType::ServerMessage => Some(CallState::Response),
+        EventType::ClientHalfClose => Some(CallState::HalfClosed),
+        EventType::ServerTrailer => Some(CallState::Trailer),
+        EventType::Cancel => Some(CallState::Cancelled),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::*;
+
+    const METHOD_NAME: &str = "/influxdata.platform.storage.Storage/Capabilities";
+
+    /// An entry of `event_type` logged by `logger`, with a payload
+    /// typical for it
+    fn logged(logger: Logger, call_id: u64, sequence_id: u64, event_type: EventType) -> Entry {
+        let payload = match event_type {
+            EventType::ClientHeader => Payload::ClientHeader(ClientHeader {
+                metadata: HashMap::new(),
+                method_name: METHOD_NAME.to_string(),
+                authority: "storage:8082".to_string(),
+                timeout: None,
+            }),
+            EventType::ServerHeader => Payload::ServerHeader(ServerHeader {
+                metadata: HashMap::new(),
+            }),
+            EventType::ClientMessage | EventType::ServerMessage => Payload::Message(Message {
+                length: 0,
+                data: vec![],
+            }),
+            EventType::ServerTrailer => Payload::Trailer(Trailer {
+                metadata: HashMap::new(),
+                status_code: 0,
+                status_message: String::new(),
+                status_details: vec![],
+            }),
+            _ => Payload::Empty,
+        };
+
+        Entry {
+            timestamp: Some(
+                Utc.timestamp(1_644_522_055, 0) + Duration::milliseconds(sequence_id as i64),
+            ),
+            call_id,
+            sequence_id_within_call: sequence_id,
+            event_type,
+            logger,
+            payload_truncated: false,
+            peer: None,
+            payload,
+        }
+    }
+
+    fn client(call_id: u64, sequence_id: u64, event_type: EventType) -> Entry {
+        logged(Logger::Client, call_id, sequence_id, event_type)
+    }
+
+    fn ids(calls: impl IntoIterator<Item = Call>) -> Vec<u64> {
+        calls.into_iter().map(|call| call.id).collect()
+    }
+
+    #[test]
+    fn interleaved_calls_complete_out_of_order() {
+        let mut builder = CallsBuilder::new();
+
+        assert!(builder
+            .push(client(1, 1, EventType::ClientHeader))
+            .is_empty());
+        assert!(builder
+            .push(client(2, 1, EventType::ClientHeader))
+            .is_empty());
+        assert!(builder
+            .push(client(1, 2, EventType::ClientMessage))
+            .is_empty());
+        assert!(builder
+            .push(client(2, 2, EventType::ClientMessage))
+            .is_empty());
+        assert_eq!(builder.in_flight(), 2);
+
+        let completed = builder.push(client(2, 3, EventType::ServerTrailer));
+        assert_eq!(ids(completed), vec![2]);
+        assert_eq!(builder.in_flight(), 1);
+
+        let completed = builder.push(client(1, 3, EventType::ServerTrailer));
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].id, 1);
+        assert!(completed[0].request.is_some());
+        assert_eq!(builder.in_flight(), 0);
+        assert_eq!(builder.finish().count(), 0);
+    }
+
+    #[test]
+    fn calls_still_open_are_returned_by_finish() {
+        let mut builder = CallsBuilder::new();
+        builder.push(client(2, 1, EventType::ClientHeader));
+        builder.push(client(1, 1, EventType::ClientHeader));
+        builder.push(client(1, 2, EventType::ClientMessage));
+        builder.push(client(3, 1, EventType::ClientHeader));
+        builder.push(client(3, 2, EventType::ServerTrailer));
+        assert_eq!(builder.in_flight(), 2);
+
+        assert_eq!(ids(builder.finish()), vec![1, 2]);
+    }
+
+    #[test]
+    fn cancelled_calls_are_held_back() {
+        let mut builder = CallsBuilder::new();
+        builder.push(client(1, 1, EventType::ClientHeader));
+        assert!(builder.push(client(1, 2, EventType::Cancel)).is_empty());
+        // no longer in flight, but not handed back yet either
+        assert_eq!(builder.in_flight(), 0);
+
+        // the trailer that was already on its way ends up in the call
+        let completed = builder.push(client(1, 3, EventType::ServerTrailer));
+        assert_eq!(ids(completed.clone()), vec![1]);
+        assert_eq!(completed[0].status_code, Some(0));
+        assert_eq!(builder.finish().count(), 0);
+
+        let mut builder = CallsBuilder::new();
+        builder.push(client(1, 1, EventType::ClientHeader));
+        builder.push(client(1, 2, EventType::Cancel));
+        assert_eq!(ids(builder.finish()), vec![1]);
+    }
+
+    #[test]
+    fn held_back_cancelled_calls_are_capped() {
+        let mut builder = CallsBuilder::new();
+        let mut completed = vec![];
+        for call_id in 1..=(MAX_HELD_CANCELLED_CALLS as u64 + 2) {
+            completed.extend(builder.push(client(call_id, 1, EventType::ClientHeader)));
+            completed.extend(builder.push(client(call_id, 2, EventType::Cancel)));
+        }
+
+        // the oldest are handed back once too many are held
+        assert_eq!(ids(completed), vec![1, 2]);
+        assert_eq!(builder.finish().count(), MAX_HELD_CANCELLED_CALLS);
+    }
+
+    #[test]
+    fn calls_from_entries_are_in_id_order() {
+        let calls: Calls = vec![
+            client(2, 1, EventType::ClientHeader),
+            client(1, 1, EventType::ClientHeader),
+            client(2, 2, EventType::ServerTrailer),
+            client(3, 1, EventType::ClientHeader),
+            client(1, 2, EventType::ServerTrailer),
+        ]
+        .into_iter()
+        .collect();
+
+        let ids: Vec<_> = calls.iter().map(|call| call.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn anomalies_are_recorded_and_summarized() {
+        let mut duplicate_header = client(1, 2, EventType::ClientHeader);
+        if let Payload::ClientHeader(header) = &mut duplicate_header.payload {
+            header
+                .metadata
+                .insert("user-agent".to_string(), "test".to_string());
+        }
+        let mut first_header = client(1, 1, EventType::ClientHeader);
+        if let Payload::ClientHeader(header) = &mut first_header.payload {
+            header
+                .metadata
+                .insert("user-agent".to_string(), "test".to_string());
+        }
+
+        let calls: Calls = vec![
+            first_header,
+            duplicate_header,
+            client(1, 3, EventType::ServerTrailer),
+            // no header, so the message can't be decoded
+            client(2, 1, EventType::ClientMessage),
+            client(2, 2, EventType::ServerTrailer),
+            logged(Logger::Unknown, 3, 1, EventType::ClientHeader),
+            logged(Logger::Unknown, 3, 2, EventType::ServerTrailer),
+        ]
+        .into_iter()
+        .collect();
+
+        let kinds = |id: u64| -> Vec<AnomalyKind> {
+            let call = calls.iter().find(|call| call.id == id).unwrap();
+            call.anomalies.iter().map(|anomaly| anomaly.kind).collect()
+        };
+        assert_eq!(
+            kinds(1),
+            vec![
+                AnomalyKind::DuplicateMethodName,
+                AnomalyKind::DuplicateAuthority,
+                AnomalyKind::DuplicateClientHeader,
+            ]
+        );
+        assert_eq!(kinds(2), vec![AnomalyKind::MessageBeforeHeader]);
+        assert_eq!(
+            kinds(3),
+            vec![AnomalyKind::UnexpectedLogger, AnomalyKind::UnexpectedLogger]
+        );
+
+        let summary: Vec<_> = calls.anomaly_summary().into_iter().collect();
+        assert_eq!(
+            summary,
+            vec![
+                (AnomalyKind::UnexpectedLogger, 2),
+                (AnomalyKind::MessageBeforeHeader, 1),
+                (AnomalyKind::DuplicateMethodName, 1),
+                (AnomalyKind::DuplicateAuthority, 1),
+                (AnomalyKind::DuplicateClientHeader, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn client_and_server_calls_with_the_same_id_stay_separate() {
+        let server = |sequence_id, event_type| logged(Logger::Server, 1, sequence_id, event_type);
+        let calls: Calls = vec![
+            client(1, 1, EventType::ClientHeader),
+            server(1, EventType::ClientHeader),
+            client(1, 2, EventType::ClientMessage),
+            server(2, EventType::ClientMessage),
+            server(3, EventType::ServerTrailer),
+            client(1, 3, EventType::ServerTrailer),
+        ]
+        .into_iter()
+        .collect();
+
+        let calls: Vec<_> = calls.iter().collect();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].logged_by, Logger::Client);
+        assert_eq!(calls[1].logged_by, Logger::Server);
+        for call in calls {
+            assert_eq!(call.id, 1);
+            assert!(call.anomalies.is_empty(), "{:?}", call.anomalies);
+            assert_eq!(call.lifecycle.outcome(), Outcome::Completed);
+        }
+    }
+
+    #[test]
+    fn outcomes_are_recorded_and_summarized() {
+        let calls: Calls = vec![
+            // completed
+            client(1, 1, EventType::ClientHeader),
+            client(1, 2, EventType::ClientMessage),
+            client(1, 3, EventType::ClientHalfClose),
+            client(1, 4, EventType::ServerHeader),
+            client(1, 5, EventType::ServerMessage),
+            client(1, 6, EventType::ServerTrailer),
+            // cancelled
+            client(2, 1, EventType::ClientHeader),
+            client(2, 2, EventType::ClientMessage),
+            client(2, 3, EventType::Cancel),
+            // half closed, but no trailer before the capture ended
+            client(3, 1, EventType::ClientHeader),
+            client(3, 2, EventType::ClientMessage),
+            client(3, 3, EventType::ClientHalfClose),
+            // incomplete
+            client(4, 1, EventType::ClientHeader),
+        ]
+        .into_iter()
+        .collect();
+
+        let outcomes: Vec<_> = calls
+            .iter()
+            .map(|call| (call.id, call.lifecycle.outcome()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                (1, Outcome::Completed),
+                (2, Outcome::Cancelled),
+                (3, Outcome::Incomplete),
+                (4, Outcome::Incomplete),
+            ]
+        );
+
+        let completed = calls.iter().next().unwrap();
+        let states: Vec<_> = completed
+            .lifecycle
+            .transitions
+            .iter()
+            .map(|transition| transition.state)
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                CallState::ClientHeaders,
+                CallState::Request,
+                CallState::HalfClosed,
+                CallState::ServerHeaders,
+                CallState::Response,
+                CallState::Trailer,
+            ]
+        );
+
+        let summary: Vec<_> = calls.outcome_summary().into_iter().collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Outcome::Completed, 1),
+                (Outcome::Cancelled, 1),
+                (Outcome::Incomplete, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_methods_are_summarized() {
+        let decoders = MethodRegistry::default();
+        let call = |id, method_name: &str| {
+            let mut call = Call::new(id);
+            call.with_method_name(method_name.to_string())
+                .with_request_data(vec![], &decoders)
+                .with_response_data(vec![], &decoders)
+                .with_response_data(vec![], &decoders);
+            call
+        };
+        let calls = Calls::from(vec![
+            call(1, "/arrow.flight.protocol.FlightService/DoGet"),
+            call(2, METHOD_NAME),
+            call(3, "/arrow.flight.protocol.FlightService/DoGet"),
+        ]);
+
+        let summary: Vec<_> = calls.unknown_summary().into_iter().collect();
+        assert_eq!(
+            summary,
+            vec![("/arrow.flight.protocol.FlightService/DoGet".to_string(), 6)]
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Compares the gRPC calls found in two captures
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    call::Call,
+    calls::Calls,
+    decoder::MethodRegistry,
+    diff::{diff_responses, DiffOptions},
+    dump_calls::DumpCalls,
+    error::Result,
+    matcher::Matcher,
+    view::MethodView,
+};
+
+pub struct Compare {
+    left_path: PathBuf,
+    right_path: PathBuf,
+    diff_options: DiffOptions,
+    matcher: Matcher,
+    decoders: MethodRegistry,
+}
+
+impl Compare {
+    /// Compares the calls found in `left_path` with the ones found in
+    /// `right_path`. Each path can be either a directory of grpc log
+    /// files or a binary file written by `dump-calls --format bin`
+    pub fn new(left_path: impl Into<PathBuf>, right_path: impl Into<PathBuf>) -> Self {
+        let left_path = left_path.into();
+        let right_path = right_path.into();
+
+        Self {
+            left_path,
+            right_path,
+            diff_options: DiffOptions::default(),
+            matcher: Matcher::new(),
+            decoders: MethodRegistry::default(),
+        }
+    }
+
+    /// Set the matcher used to pair calls across captures
+    pub fn with_matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = matcher;
+        self
+    }
+
+    /// Decode the requests and responses of logged calls with `decoders`
+    pub fn with_decoders(mut self, decoders: MethodRegistry) -> Self {
+        self.decoders = decoders;
+        self
+    }
+
+    /// Set the options used to compare the responses of paired calls
+    pub fn with_diff_options(mut self, diff_options: DiffOptions) -> Self {
+        self.diff_options = diff_options;
+        self
+    }
+
+    /// Loads the (left, right) [`Calls`] to compare
+    pub fn load(&self) -> Result<(Calls, Calls)> {
+        let left = load_calls(&self.left_path, &self.decoders)?;
+        let right = load_calls(&self.right_path, &self.decoders)?;
+        Ok((left, right))
+    }
+
+    /// Pairs each call in `left` with an equivalent call in `right`
+    /// and compares their responses
+    pub fn compare<'a>(&self, left: &'a Calls, right: &'a Calls) -> Comparison<'a> {
+        let matches = self.matcher.match_calls(left, right);
+
+        let pairs = matches
+            .pairs
+            .into_iter()
+            .map(|(left_call, right_call)| CallPair::new(left_call, right_call, &self.diff_options))
+            .collect();
+
+        Comparison {
+            left_len: left.len(),
+            right_len: right.len(),
+            pairs,
+            unmatched_left: matches.unmatched_left,
+            unmatched_right: matches.unmatched_right,
+        }
+    }
+}
+
+/// Loads [`Calls`] from a directory of grpc log files, or from a
+/// binary file written by [`DumpCalls::write_calls_binary`]
+pub fn load_calls(path: &Path, decoders: &MethodRegistry) -> Result<Calls> {
+    let is_log_path = path.is_dir()
+        || path
+            .extension()
+            .map(|extension| extension == "txt")
+            .unwrap_or(false);
+
+    let mut calls = if is_log_path {
+        DumpCalls::new(path)
+            .with_decoders(decoders.clone())
+            .process()?
+    } else {
+        println!("Reading binary calls from {:?}", path);
+        let mut calls = DumpCalls::read_calls_binary(path)?;
+        // they were written with whatever decoders were used back then
+        calls.decode_unknown(decoders);
+        calls
+    };
+
+    calls.filter_offset_calls();
+    println!("Loaded {} calls from {:?}", calls.len(), path);
+    Ok(calls)
+}
+
+/// A call from the left capture paired with a call from the right
+/// capture that was sent an equivalent request
+#[derive(Debug)]
+pub struct CallPair<'a> {
+    pub left: &'a Call,
+    pub right: &'a Call,
+
+    /// Differences found between the status and responses of the two calls
+    pub differences: Vec<String>,
+
+    /// Number of float values that only matched within the configured tolerance
+    pub tolerance_matches: usize,
+
+    /// Responses that were truncated in the capture, so were only
+    /// compared up to where they were cut off
+    pub truncated: Vec<String>,
+}
+
+impl<'a> CallPair<'a> {
+    pub fn new(left: &'a Call, right: &'a Call, options: &DiffOptions) -> Self {
+        let mut differences = vec![];
+
+        if left.status_code != right.status_code {
+            differences.push(format!(
+                "status: {:?} ({:?}) vs {:?} ({:?})",
+                left.status_code, left.status_message, right.status_code, right.status_message
+            ));
+        }
+        let diff = diff_responses(&left.responses, &right.responses, options);
+        differences.extend(diff.differences);
+
+        Self {
+            left,
+            right,
+            differences,
+            tolerance_matches: diff.tolerance_matches,
+            truncated: diff.truncated,
+        }
+    }
+
+    /// Did both sides get the same status and responses?
+    pub fn is_same(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// The result of comparing two sets of [`Calls`]
+#[derive(Debug)]
+pub struct Comparison<'a> {
+    left_len: usize,
+    right_len: usize,
+
+    /// Calls that were paired up across captures
+    pub pairs: Vec<CallPair<'a>>,
+
+    /// Calls in the left capture with no equivalent in the right capture
+    pub unmatched_left: Vec<&'a Call>,
+
+    /// Calls in the right capture with no equivalent in the left capture
+    pub unmatched_right: Vec<&'a Call>,
+}
+
+impl<'a> Comparison<'a> {
+    /// Pairs of calls whose responses differ
+    pub fn differences(&self) -> impl Iterator<Item = &CallPair<'a>> {
+        self.pairs.iter().filter(|pair| !pair.is_same())
+    }
+
+    pub fn write_report<W>(&self, out: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        let num_different = self.differences().count();
+
+        writeln!(
+            out,
+            "Compared {} left calls with {} right calls",
+            self.left_len, self.right_len
+        )?;
+        writeln!(
+            out,
+            "  {} paired calls: {} same, {} different",
+            self.pairs.len(),
+            self.pairs.len() - num_different,
+            num_different
+        )?;
+        writeln!(
+            out,
+            "  {} unmatched left calls, {} unmatched right calls",
+            self.unmatched_left.len(),
+            self.unmatched_right.len()
+        )?;
+
+        let num_truncated = self
+            .pairs
+            .iter()
+            .filter(|pair| !pair.truncated.is_empty())
+            .count();
+        if num_truncated > 0 {
+            writeln!(
+                out,
+                "  {} paired calls with truncated responses were only partially compared",
+                num_truncated
+            )?;
+        }
+
+        let tolerance_matches: usize = self.pairs.iter().map(|pair| pair.tolerance_matches).sum();
+        if tolerance_matches > 0 {
+            writeln!(
+                out,
+                "  {} float values only matched within tolerance",
+                tolerance_matches
+            )?;
+        }
+
+        for pair in self.differences() {
+            writeln!(out, "\nResponses differ:")?;
+            writeln!(out, "  left:  {}", pair.left)?;
+            writeln!(out, "  right: {}", pair.right)?;
+            write_request(out, "  ", pair.left)?;
+            for difference in &pair.differences {
+                writeln!(out, "  {}", difference)?;
+            }
+        }
+
+        for pair in self.pairs.iter().filter(|pair| !pair.truncated.is_empty()) {
+            writeln!(out, "\nResponses truncated, partially compared:")?;
+            writeln!(out, "  left:  {}", pair.left)?;
+            writeln!(out, "  right: {}", pair.right)?;
+            for truncated in &pair.truncated {
+                writeln!(out, "  {}", truncated)?;
+            }
+        }
+
+        if !self.unmatched_left.is_empty() {
+            writeln!(out, "\nUnmatched left calls:")?;
+            for call in &self.unmatched_left {
+                writeln!(out, "  {}", call)?;
+                write_request(out, "    ", call)?;
+            }
+        }
+
+        if !self.unmatched_right.is_empty() {
+            writeln!(out, "\nUnmatched right calls:")?;
+            for call in &self.unmatched_right {
+                writeln!(out, "  {}", call)?;
+                write_request(out, "    ", call)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_request<W: Write>(out: &mut W, indent: &str, call: &Call) -> Result<()> {
+    match &call.request {
+        Some(request) => writeln!(out, "{}request: {}", indent, MethodView(request))?,
+        None => writeln!(out, "{}request: <none>", indent)?,
+    }
+    Ok(())
+}
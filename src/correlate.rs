@@ -0,0 +1,243 @@
+//! Joins the client side and server side captures of the same calls
+
+use std::{collections::HashMap, io::Write};
+
+use bytes::Bytes;
+use chrono::Duration;
+
+use crate::{
+    call::Call, calls::Calls, entry::Logger, error::Result, matcher::TRACE_ID_HEADER_NAME,
+};
+
+/// Identifies the client and server side views of the same call
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct CorrelationKey {
+    trace_id: Option<String>,
+    method_name: Option<String>,
+    request: Option<Bytes>,
+}
+
+impl CorrelationKey {
+    fn new(call: &Call) -> Self {
+        Self {
+            trace_id: call.client_headers.get(TRACE_ID_HEADER_NAME).cloned(),
+            method_name: call.method_name.clone(),
+            request: call.request_data.clone(),
+        }
+    }
+}
+
+/// A call as logged by the client, and as logged by the server
+#[derive(Debug)]
+pub struct CorrelatedCall<'a> {
+    pub client: &'a Call,
+    pub server: &'a Call,
+}
+
+impl<'a> CorrelatedCall<'a> {
+    /// Time from the client starting the call until the server saw it
+    /// (network and queueing on the way in)
+    pub fn request_latency(&self) -> Option<Duration> {
+        Some(self.server.start_time? - self.client.start_time?)
+    }
+
+    /// Time from the server finishing the call until the client saw it
+    /// finish (network and queueing on the way out)
+    pub fn response_latency(&self) -> Option<Duration> {
+        Some(self.client.end_time? - self.server.end_time?)
+    }
+}
+
+/// Client and server side calls, joined up
+#[derive(Debug, Default)]
+pub struct Correlation<'a> {
+    pub pairs: Vec<CorrelatedCall<'a>>,
+
+    /// Client side calls with no server side counterpart
+    pub unmatched_client: Vec<&'a Call>,
+
+    /// Server side calls with no client side counterpart
+    pub unmatched_server: Vec<&'a Call>,
+}
+
+/// Pairs each client logged call in `calls` with the server logged call
+/// that has the same trace id, method and request. When there are
+/// several candidates, the one that started closest in time is used.
+pub fn correlate(calls: &Calls) -> Correlation<'_> {
+    let mut server_by_key: HashMap<CorrelationKey, Vec<&Call>> = HashMap::new();
+    let mut client_calls = vec![];
+    for call in calls.iter() {
+        match call.logged_by {
+            Logger::Client => client_calls.push(call),
+            Logger::Server => server_by_key
+                .entry(CorrelationKey::new(call))
+                .or_default()
+                .push(call),
+            Logger::Unknown => {}
+        }
+    }
+
+    let mut correlation = Correlation::default();
+    client_calls.sort_by_key(|call| call.start_time);
+    for client in client_calls {
+        let server = server_by_key
+            .get_mut(&CorrelationKey::new(client))
+            .and_then(|candidates| {
+                let closest = candidates
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, server)| start_distance(client, server))
+                    .map(|(i, _)| i)?;
+                Some(candidates.swap_remove(closest))
+            });
+
+        match server {
+            Some(server) => correlation.pairs.push(CorrelatedCall { client, server }),
+            None => correlation.unmatched_client.push(client),
+        }
+    }
+
+    correlation.unmatched_server = server_by_key.into_values().flatten().collect();
+    correlation
+        .unmatched_server
+        .sort_by_key(|call| call.start_time);
+
+    correlation
+}
+
+/// How far apart the two calls started
+fn start_distance(client: &Call, server: &Call) -> Duration {
+    match (client.start_time, server.start_time) {
+        (Some(client), Some(server)) => (server - client).max(client - server),
+        // calls without timestamps are the worst candidates
+        _ => Duration::max_value(),
+    }
+}
+
+impl<'a> Correlation<'a> {
+    pub fn write_report<W>(&self, out: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        writeln!(
+            out,
+            "Correlated {} calls, {} unmatched client calls, {} unmatched server calls",
+            self.pairs.len(),
+            self.unmatched_client.len(),
+            self.unmatched_server.len()
+        )?;
+
+        let request_latencies: Vec<_> = self
+            .pairs
+            .iter()
+            .filter_map(|pair| pair.request_latency())
+            .collect();
+        let response_latencies: Vec<_> = self
+            .pairs
+            .iter()
+            .filter_map(|pair| pair.response_latency())
+            .collect();
+        writeln!(
+            out,
+            "  request latency (client start to server start): {}",
+            summarize(request_latencies)
+        )?;
+        writeln!(
+            out,
+            "  response latency (server end to client end): {}",
+            summarize(response_latencies)
+        )?;
+
+        if !self.pairs.is_empty() {
+            writeln!(out, "\nCorrelated calls:")?;
+            for pair in &self.pairs {
+                writeln!(out, "  client: {}", pair.client)?;
+                writeln!(out, "  server: {}", pair.server)?;
+                writeln!(
+                    out,
+                    "    request latency: {}, response latency: {}",
+                    format_latency(pair.request_latency()),
+                    format_latency(pair.response_latency())
+                )?;
+            }
+        }
+
+        if !self.unmatched_client.is_empty() {
+            writeln!(out, "\nUnmatched client calls:")?;
+            for call in &self.unmatched_client {
+                writeln!(out, "  {}", call)?;
+            }
+        }
+
+        if !self.unmatched_server.is_empty() {
+            writeln!(out, "\nUnmatched server calls:")?;
+            for call in &self.unmatched_server {
+                writeln!(out, "  {}", call)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// min / median / max of `latencies`
+fn summarize(mut latencies: Vec<Duration>) -> String {
+    if latencies.is_empty() {
+        return "n/a".to_string();
+    }
+    latencies.sort();
+
+    format!(
+        "min {}, median {}, max {}",
+        format_latency(latencies.first().copied()),
+        format_latency(latencies.get(latencies.len() / 2).copied()),
+        format_latency(latencies.last().copied())
+    )
+}
+
+fn format_latency(latency: Option<Duration>) -> String {
+    match latency.and_then(|latency| latency.num_microseconds()) {
+        Some(micros) => format!("{:.3}ms", micros as f64 / 1000.0),
+        None => "??".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    /// A call logged by `logged_by` that started at `start_ms` and took 10ms
+    fn call(id: u64, logged_by: Logger, start_ms: i64, request: &'static [u8]) -> Call {
+        let start = Utc.timestamp_millis(start_ms);
+        let mut call = Call::new(id);
+        call.with_logged_by(logged_by)
+            .with_timestamp(Some(start))
+            .with_timestamp(Some(start + Duration::milliseconds(10)));
+        call.method_name = Some("/influxdata.platform.storage.Storage/ReadFilter".to_string());
+        call.request_data = Some(Bytes::from_static(request));
+        call
+    }
+
+    #[test]
+    fn closest_server_call_is_correlated() {
+        let calls = Calls::from(vec![
+            call(1, Logger::Client, 1_000, b"request"),
+            call(2, Logger::Client, 1_000, b"other request"),
+            call(11, Logger::Server, 5_000, b"request"),
+            call(12, Logger::Server, 1_002, b"request"),
+        ]);
+
+        let correlation = correlate(&calls);
+        assert_eq!(correlation.pairs.len(), 1);
+        let pair = &correlation.pairs[0];
+        assert_eq!((pair.client.id, pair.server.id), (1, 12));
+        assert_eq!(pair.request_latency(), Some(Duration::milliseconds(2)));
+        assert_eq!(pair.response_latency(), Some(Duration::milliseconds(-2)));
+
+        let ids = |calls: &[&Call]| calls.iter().map(|call| call.id).collect::<Vec<_>>();
+        assert_eq!(ids(&correlation.unmatched_client), vec![2]);
+        assert_eq!(ids(&correlation.unmatched_server), vec![11]);
+    }
+}
@@ -0,0 +1,227 @@
+//! Decoding of gRPC messages into [`Method`]s, by method name
+//!
+//! Decoders for additional services are added to a [`MethodRegistry`]
+//! for either a single method or a whole service:
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use bytes::Bytes;
+//! use influxrpc_compare::{
+//!     decoder::{MethodDecoder, MethodRegistry},
+//!     methods::{Method, MethodType},
+//! };
+//!
+//! /// Shows the messages as a raw tree of field numbers and values
+//! #[derive(Debug)]
+//! struct RawDecoder;
+//!
+//! impl MethodDecoder for RawDecoder {
+//!     fn decode(&self, method_name: &str, bytes: &Bytes, _: MethodType) -> Result<Method, String> {
+//!         Ok(Method::Unknown {
+//!             method_name: method_name.to_string(),
+//!             bytes: bytes.clone(),
+//!         })
+//!     }
+//! }
+//!
+//! let decoders = MethodRegistry::default()
+//!     .with_service("influxdata.iox.management.v1.ManagementService", Arc::new(RawDecoder))
+//!     .with_method("/arrow.flight.protocol.FlightService/DoGet", Arc::new(RawDecoder));
+//!
+//! assert!(decoders.knows("/arrow.flight.protocol.FlightService/DoGet"));
+//! assert!(decoders.knows("/influxdata.iox.management.v1.ManagementService/GetDatabase"));
+//! assert!(!decoders.knows("/arrow.flight.protocol.FlightService/DoPut"));
+//! ```
+
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use bytes::Bytes;
+
+use crate::{
+    methods::{Method, MethodType, StorageDecoder, STORAGE_SERVICE_NAME},
+    wire::complete_prefix_len,
+};
+
+/// Decodes the requests and responses of one or more gRPC methods
+pub trait MethodDecoder: Debug + Send + Sync {
+    /// Decodes a request or response of `method_name` (e.g.
+    /// `/influxdata.platform.storage.Storage/ReadFilter`), returning why
+    /// if `bytes` could not be decoded. Methods the decoder does not
+    /// know should be returned as [`Method::Unknown`].
+    fn decode(
+        &self,
+        method_name: &str,
+        bytes: &Bytes,
+        method_type: MethodType,
+    ) -> Result<Method, String>;
+}
+
+/// The decoders to use for each gRPC method. Methods without a decoder
+/// of their own use the decoder of their service, if any, and are kept
+/// as [`Method::Unknown`] otherwise.
+#[derive(Debug, Clone)]
+pub struct MethodRegistry {
+    /// By full method name, e.g. `/influxdata.platform.storage.Storage/ReadFilter`
+    methods: HashMap<String, Arc<dyn MethodDecoder>>,
+    /// By service name, e.g. `influxdata.platform.storage.Storage`
+    services: HashMap<String, Arc<dyn MethodDecoder>>,
+}
+
+impl Default for MethodRegistry {
+    /// Knows the storage service
+    fn default() -> Self {
+        Self::empty().with_service(STORAGE_SERVICE_NAME, Arc::new(StorageDecoder))
+    }
+}
+
+impl MethodRegistry {
+    /// A registry without any decoders
+    pub fn empty() -> Self {
+        Self {
+            methods: HashMap::new(),
+            services: HashMap::new(),
+        }
+    }
+
+    /// Decode `method_name` (e.g. `/arrow.flight.protocol.FlightService/DoGet`)
+    /// with `decoder`
+    pub fn with_method(
+        mut self,
+        method_name: impl Into<String>,
+        decoder: Arc<dyn MethodDecoder>,
+    ) -> Self {
+        self.methods.insert(method_name.into(), decoder);
+        self
+    }
+
+    /// Decode all methods of `service_name` (e.g.
+    /// `arrow.flight.protocol.FlightService`) that have no decoder of
+    /// their own with `decoder`
+    pub fn with_service(
+        mut self,
+        service_name: impl Into<String>,
+        decoder: Arc<dyn MethodDecoder>,
+    ) -> Self {
+        self.services.insert(service_name.into(), decoder);
+        self
+    }
+
+    /// Is there a decoder for `method_name`, of its own or its service's?
+    pub fn knows(&self, method_name: &str) -> bool {
+        self.decoder(method_name).is_some()
+    }
+
+    fn decoder(&self, method_name: &str) -> Option<&dyn MethodDecoder> {
+        let decoder = self
+            .methods
+            .get(method_name)
+            .or_else(|| self.services.get(service_name(method_name)?))?;
+        Some(decoder.as_ref())
+    }
+
+    /// Decodes `data` sent to or from `method_name`. Messages that fail
+    /// to decode are kept as [`Method::DecodeError`], and messages of
+    /// methods without a decoder as [`Method::Unknown`].
+    pub fn decode(&self, method_name: &str, data: Vec<u8>, method_type: MethodType) -> Method {
+        let bytes: Bytes = data.into();
+
+        let decoder = match self.decoder(method_name) {
+            Some(decoder) => decoder,
+            None => {
+                return Method::Unknown {
+                    method_name: method_name.to_string(),
+                    bytes,
+                };
+            }
+        };
+
+        decoder
+            .decode(method_name, &bytes, method_type)
+            .unwrap_or_else(|error| Method::DecodeError {
+                method_name: method_name.to_string(),
+                method_type,
+                bytes,
+                error,
+            })
+    }
+
+    /// A message of which only the first `data` bytes of `length`
+    /// were captured
+    pub fn decode_truncated(
+        &self,
+        method_name: &str,
+        length: u32,
+        data: Vec<u8>,
+        method_type: MethodType,
+    ) -> Method {
+        let bytes: Bytes = data.into();
+
+        let complete = complete_prefix_len(&bytes);
+        let partial = (complete > 0)
+            .then(|| self.decode(method_name, bytes[..complete].to_vec(), method_type))
+            // only keep what could be decoded
+            .filter(|method| !method.is_decode_error())
+            .map(Box::new);
+
+        Method::Truncated {
+            length,
+            bytes,
+            partial,
+        }
+    }
+}
+
+/// `influxdata.platform.storage.Storage` for `/influxdata.platform.storage.Storage/ReadFilter`
+fn service_name(method_name: &str) -> Option<&str> {
+    let (service_name, _) = method_name.strip_prefix('/')?.split_once('/')?;
+    Some(service_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const READ_FILTER: &str = "/influxdata.platform.storage.Storage/ReadFilter";
+
+    #[test]
+    fn malformed_messages_are_kept() {
+        // a length delimited field that claims more bytes than there are
+        let data = vec![0x0a, 0x05, 0x01];
+        let decoded =
+            MethodRegistry::default().decode(READ_FILTER, data.clone(), MethodType::Request);
+
+        match decoded {
+            Method::DecodeError {
+                method_name,
+                method_type,
+                bytes,
+                error,
+            } => {
+                assert_eq!(method_name, READ_FILTER);
+                assert_eq!(method_type, MethodType::Request);
+                assert_eq!(bytes, data);
+                assert!(
+                    error.starts_with("Error decoding ReadFilterRequest"),
+                    "{}",
+                    error
+                );
+            }
+            method => panic!("expected a decode error, got {:?}", method),
+        }
+    }
+
+    #[test]
+    fn unknown_methods_are_kept() {
+        let method_name = "/arrow.flight.protocol.FlightService/DoGet";
+        let decoded =
+            MethodRegistry::default().decode(method_name, vec![1, 2], MethodType::Request);
+        assert_eq!(
+            decoded,
+            Method::Unknown {
+                method_name: method_name.to_string(),
+                bytes: Bytes::from_static(&[1, 2]),
+            }
+        );
+    }
+}
@@ -0,0 +1,607 @@
+//! Decoding of messages we have no compiled Rust types for, using the
+//! definitions in a `FileDescriptorSet` loaded at runtime (e.g. written
+//! by `protoc --include_imports --descriptor_set_out=foo.pb foo.proto`)
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use prost_types::{
+    field_descriptor_proto::Type, DescriptorProto, EnumDescriptorProto, FieldDescriptorProto,
+    FileDescriptorSet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    decoder::{MethodDecoder, MethodRegistry},
+    error::Result,
+    methods::{Method, MethodType},
+    wire::{
+        escape_bytes, unpack_fixed32, unpack_fixed64, unpack_varints, Field, FieldValue, Fields,
+    },
+};
+
+/// Message, enum and service definitions from a `FileDescriptorSet`
+#[derive(Debug, Default)]
+pub struct Descriptors {
+    /// By fully qualified name, e.g. `.influxdata.iox.management.v1.ListDatabasesRequest`
+    messages: HashMap<String, DescriptorProto>,
+    /// By fully qualified name
+    enums: HashMap<String, EnumDescriptorProto>,
+    /// (input type, output type) by method name, e.g.
+    /// `/influxdata.iox.management.v1.ManagementService/ListDatabases`
+    methods: BTreeMap<String, (String, String)>,
+}
+
+impl Descriptors {
+    /// Loads a compiled descriptor set, or a `.proto` file, which is
+    /// compiled with `protoc` (or `$PROTOC`) first, looking for the
+    /// files it imports in `proto_paths` and the directory it is in
+    pub fn load(path: &Path, proto_paths: &[PathBuf]) -> Result<Self> {
+        use prost::Message;
+
+        let bytes = if path.extension().map(|ext| ext == "proto").unwrap_or(false) {
+            compile_proto(path, proto_paths)?
+        } else {
+            std::fs::read(path)
+                .map_err(|e| format!("Error reading descriptors from {:?}: {}", path, e))?
+        };
+
+        let set = FileDescriptorSet::decode(&bytes[..])
+            .map_err(|e| format!("Error decoding descriptors from {:?}: {}", path, e))?;
+        let descriptors = Self::from_file_descriptor_set(set);
+        println!(
+            "Loaded {} messages and {} methods from {:?}",
+            descriptors.messages.len(),
+            descriptors.methods.len(),
+            path
+        );
+        Ok(descriptors)
+    }
+
+    pub fn from_file_descriptor_set(set: FileDescriptorSet) -> Self {
+        let mut descriptors = Self::default();
+
+        for file in set.file {
+            let prefix = match file.package() {
+                "" => String::new(),
+                package => format!(".{}", package),
+            };
+            descriptors.add_messages(&prefix, &file.message_type);
+            descriptors.add_enums(&prefix, &file.enum_type);
+
+            for service in &file.service {
+                // the method name has no leading `.`
+                let service_name = format!("{}.{}", prefix, service.name());
+                let service_name = service_name.trim_start_matches('.');
+                for method in &service.method {
+                    descriptors.methods.insert(
+                        format!("/{}/{}", service_name, method.name()),
+                        (
+                            method.input_type().to_string(),
+                            method.output_type().to_string(),
+                        ),
+                    );
+                }
+            }
+        }
+
+        descriptors
+    }
+
+    fn add_messages(&mut self, prefix: &str, messages: &[DescriptorProto]) {
+        for message in messages {
+            let name = format!("{}.{}", prefix, message.name());
+            self.add_messages(&name, &message.nested_type);
+            self.add_enums(&name, &message.enum_type);
+            self.messages.insert(name, message.clone());
+        }
+    }
+
+    fn add_enums(&mut self, prefix: &str, enums: &[EnumDescriptorProto]) {
+        for descriptor in enums {
+            let name = format!("{}.{}", prefix, descriptor.name());
+            self.enums.insert(name, descriptor.clone());
+        }
+    }
+
+    /// Decodes the methods found in the descriptors that `registry`
+    /// does not already have a decoder for
+    pub fn register(self, mut registry: MethodRegistry) -> MethodRegistry {
+        let method_names: Vec<_> = self
+            .methods
+            .keys()
+            .filter(|method_name| !registry.knows(method_name))
+            .cloned()
+            .collect();
+
+        let decoder: Arc<dyn MethodDecoder> = Arc::new(DynamicDecoder {
+            descriptors: Arc::new(self),
+        });
+        for method_name in method_names {
+            registry = registry.with_method(method_name, Arc::clone(&decoder));
+        }
+        registry
+    }
+
+    /// Decodes `bytes` as the message `type_name` (e.g. `.foo.Bar`)
+    pub fn decode_message(
+        &self,
+        type_name: &str,
+        bytes: &[u8],
+    ) -> std::result::Result<DynamicMessage, String> {
+        self.decode_message_at(type_name, bytes, 0)
+    }
+
+    /// Decodes a message nested `depth` messages deep
+    fn decode_message_at(
+        &self,
+        type_name: &str,
+        bytes: &[u8],
+        depth: usize,
+    ) -> std::result::Result<DynamicMessage, String> {
+        let descriptor = self
+            .messages
+            .get(type_name)
+            .ok_or_else(|| format!("Unknown message type {}", type_name))?;
+
+        let mut fields = Fields::new(bytes);
+        let mut decoded = vec![];
+        for Field { number, value } in fields.by_ref() {
+            let field = descriptor
+                .field
+                .iter()
+                .find(|field| field.number() as u32 == number);
+
+            match field {
+                Some(field) => self.decode_field(field, value, depth, &mut decoded)?,
+                None => decoded.push(DynamicField::unknown(number, value)),
+            }
+        }
+        if fields.offset() != bytes.len() {
+            return Err(format!(
+                "Invalid {} at byte {} of {}",
+                type_name,
+                fields.offset(),
+                bytes.len()
+            ));
+        }
+
+        Ok(DynamicMessage {
+            type_name: type_name.trim_start_matches('.').to_string(),
+            fields: decoded,
+        })
+    }
+
+    /// Decodes the value of `field`, which is several values for a
+    /// packed repeated field
+    fn decode_field(
+        &self,
+        field: &FieldDescriptorProto,
+        value: FieldValue,
+        depth: usize,
+        decoded: &mut Vec<DynamicField>,
+    ) -> std::result::Result<(), String> {
+        // as deep as `protoc` goes, so that corrupt (or crafted) payloads
+        // can not overflow the stack
+        const MAX_DEPTH: usize = 100;
+
+        let field_type = field.r#type();
+
+        let values = match (field_type, value) {
+            (Type::Message, FieldValue::LengthDelimited(bytes)) if depth < MAX_DEPTH => {
+                let message = self.decode_message_at(field.type_name(), &bytes, depth + 1)?;
+                vec![DynamicValue::Message(message)]
+            }
+            // too deep to decode, kept as raw bytes
+            (Type::Message, value @ FieldValue::LengthDelimited(_)) => {
+                vec![DynamicValue::Unknown(value)]
+            }
+            (Type::String | Type::Bytes, FieldValue::LengthDelimited(bytes)) => {
+                vec![DynamicValue::Bytes(bytes)]
+            }
+            (_, FieldValue::LengthDelimited(bytes)) => {
+                let unpacked = match field_type {
+                    Type::Double | Type::Fixed64 | Type::Sfixed64 => unpack_fixed64(&bytes)
+                        .map(|values| values.into_iter().map(FieldValue::Fixed64).collect()),
+                    Type::Float | Type::Fixed32 | Type::Sfixed32 => unpack_fixed32(&bytes)
+                        .map(|values| values.into_iter().map(FieldValue::Fixed32).collect()),
+                    _ => unpack_varints(&bytes)
+                        .map(|values| values.into_iter().map(FieldValue::Varint).collect()),
+                };
+                let unpacked: Vec<_> = unpacked
+                    .ok_or_else(|| format!("Invalid packed values for field {}", field.name()))?;
+
+                unpacked
+                    .into_iter()
+                    .map(|value| self.decode_scalar(field, value))
+                    .collect::<std::result::Result<_, _>>()?
+            }
+            (_, value) => vec![self.decode_scalar(field, value)?],
+        };
+
+        decoded.extend(values.into_iter().map(|value| DynamicField {
+            name: field.name().to_string(),
+            value,
+        }));
+        Ok(())
+    }
+
+    fn decode_scalar(
+        &self,
+        field: &FieldDescriptorProto,
+        value: FieldValue,
+    ) -> std::result::Result<DynamicValue, String> {
+        use FieldValue::*;
+
+        let decoded = match (field.r#type(), value) {
+            (Type::Double, Fixed64(v)) => DynamicValue::Double(f64::from_bits(v)),
+            (Type::Float, Fixed32(v)) => DynamicValue::Float(f32::from_bits(v)),
+            (Type::Int64, Varint(v)) => DynamicValue::Int(v as i64),
+            (Type::Int32, Varint(v)) => DynamicValue::Int(i64::from(v as i32)),
+            (Type::Uint64, Varint(v)) => DynamicValue::Uint(v),
+            (Type::Uint32, Varint(v)) => DynamicValue::Uint(u64::from(v as u32)),
+            (Type::Sint64 | Type::Sint32, Varint(v)) => {
+                DynamicValue::Int((v >> 1) as i64 ^ -((v & 1) as i64))
+            }
+            (Type::Bool, Varint(v)) => DynamicValue::Bool(v != 0),
+            (Type::Enum, Varint(v)) => DynamicValue::Enum(self.enum_name(field, v as i32)),
+            (Type::Fixed64, Fixed64(v)) => DynamicValue::Uint(v),
+            (Type::Sfixed64, Fixed64(v)) => DynamicValue::Int(v as i64),
+            (Type::Fixed32, Fixed32(v)) => DynamicValue::Uint(u64::from(v)),
+            (Type::Sfixed32, Fixed32(v)) => DynamicValue::Int(i64::from(v as i32)),
+            (field_type, value) => {
+                return Err(format!(
+                    "Unexpected {:?} value for {:?} field {}",
+                    value,
+                    field_type,
+                    field.name()
+                ))
+            }
+        };
+        Ok(decoded)
+    }
+
+    /// The name of value `number` of the enum type of `field`, or just
+    /// the number if it is not a known value
+    fn enum_name(&self, field: &FieldDescriptorProto, number: i32) -> String {
+        self.enums
+            .get(field.type_name())
+            .and_then(|descriptor| {
+                descriptor
+                    .value
+                    .iter()
+                    .find(|value| value.number() == number)
+            })
+            .map(|value| value.name().to_string())
+            .unwrap_or_else(|| number.to_string())
+    }
+}
+
+/// Runs `protoc` to compile the `.proto` file at `path` (and the files
+/// it imports) into a `FileDescriptorSet`
+fn compile_proto(path: &Path, proto_paths: &[PathBuf]) -> Result<Vec<u8>> {
+    let protoc = std::env::var("PROTOC").unwrap_or_else(|_| "protoc".to_string());
+    let out = std::env::temp_dir().join(format!("influxrpc_compare_{}.pb", std::process::id()));
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    // imports by package path (e.g. `influxdata/iox/...`) are found in
+    // `proto_paths`, and imports of sibling files in `parent`
+    let proto_paths = proto_paths
+        .iter()
+        .map(PathBuf::as_path)
+        .chain(std::iter::once(parent))
+        .map(|include| format!("--proto_path={}", include.display()));
+
+    let output = Command::new(&protoc)
+        .arg("--include_imports")
+        .arg(format!("--descriptor_set_out={}", out.display()))
+        .args(proto_paths)
+        .arg(path)
+        .output()
+        .map_err(|e| format!("Error running {}: {}", protoc, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Error compiling {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+
+    let bytes = std::fs::read(&out)?;
+    std::fs::remove_file(&out)?;
+    Ok(bytes)
+}
+
+/// Decodes the methods of a [`Descriptors`]
+#[derive(Debug)]
+struct DynamicDecoder {
+    descriptors: Arc<Descriptors>,
+}
+
+impl MethodDecoder for DynamicDecoder {
+    fn decode(
+        &self,
+        method_name: &str,
+        bytes: &Bytes,
+        method_type: MethodType,
+    ) -> std::result::Result<Method, String> {
+        let (input_type, output_type) = match self.descriptors.methods.get(method_name) {
+            Some(types) => types,
+            None => {
+                return Ok(Method::Unknown {
+                    method_name: method_name.to_string(),
+                    bytes: bytes.clone(),
+                })
+            }
+        };
+        let type_name = match method_type {
+            MethodType::Request => input_type,
+            MethodType::Response => output_type,
+        };
+
+        let message = self.descriptors.decode_message(type_name, bytes)?;
+        Ok(Method::Dynamic {
+            method_name: method_name.to_string(),
+            bytes: bytes.clone(),
+            message,
+        })
+    }
+}
+
+/// A message decoded from its descriptor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DynamicMessage {
+    /// e.g. `influxdata.iox.management.v1.ListDatabasesRequest`
+    pub type_name: String,
+    /// In the order they were encoded, with an entry for every value of
+    /// repeated fields
+    pub fields: Vec<DynamicField>,
+}
+
+impl DynamicMessage {
+    fn write_fields(&self, f: &mut std::fmt::Formatter<'_>, indent: usize) -> std::fmt::Result {
+        let pad = "  ".repeat(indent);
+        for field in &self.fields {
+            match &field.value {
+                DynamicValue::Message(message) => {
+                    writeln!(f, "{}{} {{", pad, field.name)?;
+                    message.write_fields(f, indent + 1)?;
+                    writeln!(f, "{}}}", pad)?;
+                }
+                value => writeln!(f, "{}{}: {}", pad, field.name, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats the fields like `protoc --decode`, one per line
+impl Display for DynamicMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_fields(f, 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DynamicField {
+    /// The field name, or its number if it is not in the descriptor
+    pub name: String,
+    pub value: DynamicValue,
+}
+
+impl DynamicField {
+    fn unknown(number: u32, value: FieldValue) -> Self {
+        Self {
+            name: number.to_string(),
+            value: DynamicValue::Unknown(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DynamicValue {
+    Int(i64),
+    Uint(u64),
+    Float(f32),
+    Double(f64),
+    Bool(bool),
+    /// `string` or `bytes`
+    Bytes(Bytes),
+    /// The name of the value
+    Enum(String),
+    Message(DynamicMessage),
+    /// A field that is not in the descriptor
+    Unknown(FieldValue),
+}
+
+impl Display for DynamicValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(v) => write!(f, "{}", v),
+            Self::Uint(v) => write!(f, "{}", v),
+            Self::Float(v) => write!(f, "{}", v),
+            Self::Double(v) => write!(f, "{}", v),
+            Self::Bool(v) => write!(f, "{}", v),
+            Self::Bytes(v) => write!(f, "\"{}\"", escape_bytes(v)),
+            Self::Enum(v) => write!(f, "{}", v),
+            Self::Message(v) => write!(f, "{{ {} }}", v.to_string().trim_end().replace('\n', " ")),
+            Self::Unknown(FieldValue::Varint(v)) => write!(f, "{}", v),
+            Self::Unknown(FieldValue::Fixed64(v)) => write!(f, "0x{:016x}", v),
+            Self::Unknown(FieldValue::Fixed32(v)) => write!(f, "0x{:08x}", v),
+            Self::Unknown(FieldValue::LengthDelimited(v)) => write!(f, "\"{}\"", escape_bytes(v)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost_types::{
+        field_descriptor_proto::Label, EnumValueDescriptorProto, FileDescriptorProto,
+    };
+
+    use super::*;
+
+    fn field(
+        name: &str,
+        number: i32,
+        field_type: Type,
+        type_name: Option<&str>,
+    ) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(field_type as i32),
+            type_name: type_name.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn message(name: &str, field: Vec<FieldDescriptorProto>) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            field,
+            ..Default::default()
+        }
+    }
+
+    /// ```proto
+    /// package test;
+    /// enum Kind { A = 0; B = 1; }
+    /// message Inner { string name = 1; }
+    /// message Outer { Inner inner = 1; repeated int64 values = 2; Kind kind = 3; sint32 delta = 4; }
+    /// message Node { Node child = 1; }
+    /// ```
+    fn descriptors() -> Descriptors {
+        let mut values = field("values", 2, Type::Int64, None);
+        values.label = Some(Label::Repeated as i32);
+
+        Descriptors::from_file_descriptor_set(FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                package: Some("test".to_string()),
+                message_type: vec![
+                    message("Inner", vec![field("name", 1, Type::String, None)]),
+                    message(
+                        "Outer",
+                        vec![
+                            field("inner", 1, Type::Message, Some(".test.Inner")),
+                            values,
+                            field("kind", 3, Type::Enum, Some(".test.Kind")),
+                            field("delta", 4, Type::Sint32, None),
+                        ],
+                    ),
+                    message(
+                        "Node",
+                        vec![field("child", 1, Type::Message, Some(".test.Node"))],
+                    ),
+                ],
+                enum_type: vec![EnumDescriptorProto {
+                    name: Some("Kind".to_string()),
+                    value: ["A", "B"]
+                        .iter()
+                        .zip(0..)
+                        .map(|(name, number)| EnumValueDescriptorProto {
+                            name: Some(name.to_string()),
+                            number: Some(number),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        })
+    }
+
+    fn values(message: &DynamicMessage) -> Vec<(&str, &DynamicValue)> {
+        message
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), &field.value))
+            .collect()
+    }
+
+    #[test]
+    fn decode_fields() {
+        let bytes = [
+            0x0a, 0x03, 0x0a, 0x01, b'x', // inner { name: "x" }
+            0x12, 0x03, 0x01, 0x96, 0x01, // values: [1, 150] (packed)
+            0x10, 0x02, // values: 2 (not packed)
+            0x18, 0x01, // kind: B
+            0x20, 0x03, // delta: -2 (zigzag)
+            0x48, 0x07, // field 9: 7 (unknown)
+        ];
+
+        let decoded = descriptors().decode_message(".test.Outer", &bytes).unwrap();
+        assert_eq!(decoded.type_name, "test.Outer");
+
+        let inner = DynamicValue::Message(DynamicMessage {
+            type_name: "test.Inner".to_string(),
+            fields: vec![DynamicField {
+                name: "name".to_string(),
+                value: DynamicValue::Bytes(Bytes::from_static(b"x")),
+            }],
+        });
+        assert_eq!(
+            values(&decoded),
+            vec![
+                ("inner", &inner),
+                ("values", &DynamicValue::Int(1)),
+                ("values", &DynamicValue::Int(150)),
+                ("values", &DynamicValue::Int(2)),
+                ("kind", &DynamicValue::Enum("B".to_string())),
+                ("delta", &DynamicValue::Int(-2)),
+                ("9", &DynamicValue::Unknown(FieldValue::Varint(7))),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let descriptors = descriptors();
+        assert!(descriptors.decode_message(".test.Missing", &[]).is_err());
+        // truncated length delimited field
+        assert!(descriptors
+            .decode_message(".test.Outer", &[0x0a, 0x05])
+            .is_err());
+        // fixed32 for the enum field
+        assert!(descriptors
+            .decode_message(".test.Outer", &[0x1d, 0x01, 0x00, 0x00, 0x00])
+            .is_err());
+    }
+
+    #[test]
+    fn deeply_nested_messages_are_kept_raw() {
+        let mut bytes = vec![];
+        for _ in 0..150 {
+            let mut outer = vec![0x0a];
+            prost::encoding::encode_varint(bytes.len() as u64, &mut outer);
+            outer.extend(bytes);
+            bytes = outer;
+        }
+
+        let mut message = descriptors().decode_message(".test.Node", &bytes).unwrap();
+        let mut depth = 0;
+        let raw = loop {
+            match message.fields.pop().map(|field| field.value) {
+                Some(DynamicValue::Message(child)) => message = child,
+                Some(DynamicValue::Unknown(FieldValue::LengthDelimited(raw))) => break raw,
+                value => panic!("unexpected value {:?}", value),
+            }
+            depth += 1;
+        };
+        assert_eq!(depth, 100);
+        // the rest of the nesting
+        assert_eq!(Fields::new(&raw).count(), 1);
+    }
+}
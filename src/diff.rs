@@ -0,0 +1,860 @@
+//! Structural comparison of decoded gRPC responses
+
+use std::fmt::Display;
+
+use generated_types::influxdata::platform::storage::{
+    read_response::frame::Data, ReadResponse, StringValuesResponse, Tag,
+};
+
+use crate::{
+    methods::Method,
+    view::{format_tag_keys, format_tag_values, format_tags},
+};
+
+/// Controls what is considered a difference between two responses
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiffOptions {
+    /// Ignore the order in which series (canonicalized by their tag
+    /// set), groups and string values were returned
+    pub ignore_order: bool,
+
+    /// Keep the returned order of `ReadGroup` responses (any response
+    /// with group frames) even when `ignore_order` is set, as group
+    /// order is semantically meaningful there
+    pub strict_group_order: bool,
+
+    /// Float values that differ by at most this much are considered equal
+    pub float_abs_epsilon: f64,
+
+    /// Float values that differ by at most this fraction of the
+    /// larger magnitude are considered equal
+    pub float_rel_epsilon: f64,
+}
+
+impl DiffOptions {
+    fn float_match(&self, left: f64, right: f64) -> ValueMatch {
+        // NaN == NaN for the purposes of comparison
+        if left == right || (left.is_nan() && right.is_nan()) {
+            return ValueMatch::Exact;
+        }
+
+        // infinities only ever match themselves, which is handled above
+        if !left.is_finite() || !right.is_finite() {
+            return ValueMatch::Different;
+        }
+
+        let delta = (left - right).abs();
+        if delta <= self.float_abs_epsilon
+            || delta <= self.float_rel_epsilon * left.abs().max(right.abs())
+        {
+            ValueMatch::WithinTolerance
+        } else {
+            ValueMatch::Different
+        }
+    }
+}
+
+/// The differences found between the responses of two calls
+#[derive(Debug, Default)]
+pub struct ResponseDiff {
+    /// Human readable description of each difference
+    pub differences: Vec<String>,
+
+    /// Number of float values that were only considered equal thanks
+    /// to the configured epsilon
+    pub tolerance_matches: usize,
+
+    /// Responses that were truncated in the capture, so that what
+    /// came after them was not compared
+    pub truncated: Vec<String>,
+}
+
+impl ResponseDiff {
+    fn push(&mut self, difference: String) {
+        self.differences.push(difference);
+    }
+}
+
+/// Compares the responses two calls received, returning a human
+/// readable description of each difference found (no differences if
+/// the responses are equivalent).
+///
+/// Responses split across several messages are merged before they
+/// are compared, so two servers that chunk the same logical result
+/// differently are still considered to have the same answer.
+pub fn diff_responses(left: &[Method], right: &[Method], options: &DiffOptions) -> ResponseDiff {
+    let mut diff = ResponseDiff::default();
+
+    // what is missing from a truncated response would look like a
+    // difference that isn't, so only what both sides have is compared
+    let (left, left_truncated) = complete_responses(left, "left", &mut diff);
+    let (right, right_truncated) = complete_responses(right, "right", &mut diff);
+
+    if let (Some(left), Some(right)) = (read_responses(&left), read_responses(&right)) {
+        let mut left = ReadResults::new(left);
+        let mut right = ReadResults::new(right);
+        if right_truncated {
+            left.cut_to(&right, options);
+        }
+        if left_truncated {
+            right.cut_to(&left, options);
+        }
+        if options.ignore_order {
+            left.canonicalize(options);
+            right.canonicalize(options);
+        }
+        left.diff(&right, options, &mut diff);
+    } else if let (Some(left), Some(right)) = (
+        string_values_responses(&left),
+        string_values_responses(&right),
+    ) {
+        let mut left = merge_string_values(left);
+        let mut right = merge_string_values(right);
+        if right_truncated {
+            cut_string_values(&mut left, &right, options);
+        }
+        if left_truncated {
+            cut_string_values(&mut right, &left, options);
+        }
+        if options.ignore_order {
+            left.sort_unstable();
+            right.sort_unstable();
+        }
+        diff_string_values(&left, &right, &mut diff);
+    } else if left_truncated || right_truncated {
+        // nothing to line up what was captured of other responses with
+    } else if left != right {
+        diff.push(format!("responses differ: {:?} vs {:?}", left, right));
+    }
+
+    diff
+}
+
+/// Returns the responses up to the first truncated one, which is
+/// replaced by what could be decoded of it, and whether there was one
+fn complete_responses<'a>(
+    responses: &'a [Method],
+    side: &str,
+    diff: &mut ResponseDiff,
+) -> (Vec<&'a Method>, bool) {
+    let mut complete = vec![];
+    for (i, response) in responses.iter().enumerate() {
+        if let Method::Truncated {
+            length,
+            bytes,
+            partial,
+        } = response
+        {
+            diff.truncated.push(format!(
+                "{} response {} of {} truncated ({} of {} bytes)",
+                side,
+                i + 1,
+                responses.len(),
+                bytes.len(),
+                length
+            ));
+            complete.extend(partial.as_deref());
+            return (complete, true);
+        }
+        complete.push(response);
+    }
+    (complete, false)
+}
+
+/// Returns the responses as `ReadResponse`s, if they all are
+fn read_responses<'a>(responses: &[&'a Method]) -> Option<Vec<&'a ReadResponse>> {
+    responses
+        .iter()
+        .map(|response| match response {
+            Method::ReadResponse(response) => Some(response),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the responses as `StringValuesResponse`s, if they all are
+fn string_values_responses<'a>(responses: &[&'a Method]) -> Option<Vec<&'a StringValuesResponse>> {
+    responses
+        .iter()
+        .map(|response| match response {
+            Method::StringValuesResponse(response) => Some(response),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Drops the values that `truncated` may be missing because it was
+/// cut off in the capture
+fn cut_string_values(values: &mut Vec<Vec<u8>>, truncated: &[Vec<u8>], options: &DiffOptions) {
+    if options.ignore_order {
+        values.retain(|value| truncated.contains(value));
+    } else {
+        values.truncate(truncated.len());
+    }
+}
+
+fn merge_string_values(responses: Vec<&StringValuesResponse>) -> Vec<Vec<u8>> {
+    responses
+        .into_iter()
+        .flat_map(|response| response.values.iter().cloned())
+        .collect()
+}
+
+fn diff_string_values(left: &[Vec<u8>], right: &[Vec<u8>], diff: &mut ResponseDiff) {
+    if left == right {
+        return;
+    }
+
+    let only_left: Vec<_> = left.iter().filter(|v| !right.contains(v)).collect();
+    let only_right: Vec<_> = right.iter().filter(|v| !left.contains(v)).collect();
+
+    if only_left.is_empty() && only_right.is_empty() {
+        diff.push(format!(
+            "values in different order: [{}] vs [{}]",
+            format_tag_values(left.iter()),
+            format_tag_values(right.iter())
+        ));
+    }
+    if !only_left.is_empty() {
+        diff.push(format!(
+            "values only in left: [{}]",
+            format_tag_values(only_left.into_iter())
+        ));
+    }
+    if !only_right.is_empty() {
+        diff.push(format!(
+            "values only in right: [{}]",
+            format_tag_values(only_right.into_iter())
+        ));
+    }
+}
+
+/// The frames of a `ReadResponse` stream reassembled into logical
+/// groups and series, independently of how they were split into
+/// messages and frames.
+#[derive(Debug, Default)]
+struct ReadResults {
+    groups: Vec<Group>,
+}
+
+/// Series sent before any group frame (e.g. for `ReadFilter`) belong
+/// to an implicit group without keys
+#[derive(Debug, Default)]
+struct Group {
+    tag_keys: Vec<Vec<u8>>,
+    partition_key_vals: Vec<Vec<u8>>,
+    series: Vec<Series>,
+}
+
+#[derive(Debug, Default)]
+struct Series {
+    tags: Vec<Tag>,
+    points: Vec<Point>,
+}
+
+#[derive(Debug)]
+struct Point {
+    timestamp: i64,
+    value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValueMatch {
+    Exact,
+    WithinTolerance,
+    Different,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Float(f64),
+    Integer(i64),
+    Unsigned(u64),
+    Boolean(bool),
+    String(String),
+}
+
+impl Value {
+    fn matches(&self, other: &Self, options: &DiffOptions) -> ValueMatch {
+        match (self, other) {
+            (Self::Float(left), Self::Float(right)) => options.float_match(*left, *right),
+            _ if self == other => ValueMatch::Exact,
+            _ => ValueMatch::Different,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Float(_) => "float",
+            Self::Integer(_) => "integer",
+            Self::Unsigned(_) => "unsigned",
+            Self::Boolean(_) => "boolean",
+            Self::String(_) => "string",
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Float(v) => write!(f, "{:?}", v),
+            Self::Integer(v) => write!(f, "{}", v),
+            Self::Unsigned(v) => write!(f, "{}", v),
+            Self::Boolean(v) => write!(f, "{}", v),
+            Self::String(v) => write!(f, "{:?}", v),
+        }
+    }
+}
+
+impl ReadResults {
+    fn new(responses: Vec<&ReadResponse>) -> Self {
+        let mut results = Self::default();
+
+        let frames = responses
+            .into_iter()
+            .flat_map(|response| response.frames.iter())
+            .filter_map(|frame| frame.data.as_ref());
+
+        for data in frames {
+            match data {
+                Data::Group(group) => results.groups.push(Group {
+                    tag_keys: group.tag_keys.clone(),
+                    partition_key_vals: group.partition_key_vals.clone(),
+                    series: vec![],
+                }),
+                Data::Series(series) => results.current_group().series.push(Series {
+                    tags: series.tags.clone(),
+                    points: vec![],
+                }),
+                Data::FloatPoints(frame) => results.add_points(
+                    &frame.timestamps,
+                    frame.values.iter().map(|v| Value::Float(*v)),
+                ),
+                Data::IntegerPoints(frame) => results.add_points(
+                    &frame.timestamps,
+                    frame.values.iter().map(|v| Value::Integer(*v)),
+                ),
+                Data::UnsignedPoints(frame) => results.add_points(
+                    &frame.timestamps,
+                    frame.values.iter().map(|v| Value::Unsigned(*v)),
+                ),
+                Data::BooleanPoints(frame) => results.add_points(
+                    &frame.timestamps,
+                    frame.values.iter().map(|v| Value::Boolean(*v)),
+                ),
+                Data::StringPoints(frame) => results.add_points(
+                    &frame.timestamps,
+                    frame.values.iter().map(|v| Value::String(v.clone())),
+                ),
+            }
+        }
+
+        results
+    }
+
+    /// Sorts groups by their keys (unless their order is strict) and
+    /// the series in each group by their tag set, so results can be
+    /// compared irrespective of order
+    fn canonicalize(&mut self, options: &DiffOptions) {
+        let has_group_frames = self.groups.iter().any(|group| !group.tag_keys.is_empty());
+        if !(options.strict_group_order && has_group_frames) {
+            self.groups.sort_by(|a, b| {
+                (&a.tag_keys, &a.partition_key_vals).cmp(&(&b.tag_keys, &b.partition_key_vals))
+            });
+        }
+
+        for group in &mut self.groups {
+            group.series.sort_by(|a, b| a.tag_set().cmp(&b.tag_set()));
+        }
+    }
+
+    /// Drops the groups, series and points that `truncated` may be
+    /// missing because it was cut off in the capture: everything after
+    /// its last point, or, when ignoring order, whatever it does not have
+    fn cut_to(&mut self, truncated: &Self, options: &DiffOptions) {
+        let last_series = truncated
+            .groups
+            .last()
+            .and_then(|group| Some((group, group.series.last()?)));
+
+        if options.ignore_order {
+            self.groups.retain(|group| truncated.group(group).is_some());
+            for group in &mut self.groups {
+                let other = truncated.group(group).expect("retained above");
+                group.series.retain(|series| other.series(series).is_some());
+            }
+        } else {
+            self.groups.truncate(truncated.groups.len());
+            if let (Some(group), Some(other)) = (self.groups.last_mut(), truncated.groups.last()) {
+                group.series.truncate(other.series.len());
+            }
+        }
+
+        // the last series may have been cut off part way through its points
+        if let Some((last_group, last_series)) = last_series {
+            let series = self
+                .groups
+                .iter_mut()
+                .rev()
+                .find(|group| group.has_keys_of(last_group))
+                .and_then(|group| {
+                    group
+                        .series
+                        .iter_mut()
+                        .rev()
+                        .find(|series| series.tags == last_series.tags)
+                });
+            if let Some(series) = series {
+                let last_timestamp = last_series.points.last().map(|point| point.timestamp);
+                series
+                    .points
+                    .retain(|point| Some(point.timestamp) <= last_timestamp);
+            }
+        }
+    }
+
+    fn group(&self, group: &Group) -> Option<&Group> {
+        self.groups.iter().find(|other| other.has_keys_of(group))
+    }
+
+    fn current_group(&mut self) -> &mut Group {
+        if self.groups.is_empty() {
+            self.groups.push(Group::default());
+        }
+        self.groups.last_mut().unwrap()
+    }
+
+    /// Points frames follow the series frame they belong to, possibly
+    /// split across several frames
+    fn add_points(&mut self, timestamps: &[i64], values: impl Iterator<Item = Value>) {
+        let group = self.current_group();
+        if group.series.is_empty() {
+            group.series.push(Series::default());
+        }
+        let series = group.series.last_mut().unwrap();
+
+        series.points.extend(
+            timestamps
+                .iter()
+                .zip(values)
+                .map(|(timestamp, value)| Point {
+                    timestamp: *timestamp,
+                    value,
+                }),
+        );
+    }
+
+    fn diff(&self, other: &Self, options: &DiffOptions, diff: &mut ResponseDiff) {
+        if self.groups.len() != other.groups.len() {
+            diff.push(format!(
+                "different number of groups: {} vs {}",
+                self.groups.len(),
+                other.groups.len()
+            ));
+        }
+
+        for (left, right) in self.groups.iter().zip(other.groups.iter()) {
+            left.diff(right, options, diff);
+        }
+    }
+}
+
+impl Group {
+    fn has_keys_of(&self, other: &Self) -> bool {
+        self.tag_keys == other.tag_keys && self.partition_key_vals == other.partition_key_vals
+    }
+
+    fn series(&self, series: &Series) -> Option<&Series> {
+        self.series.iter().find(|other| other.tags == series.tags)
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "group [{}]",
+            format_tag_values(self.partition_key_vals.iter())
+        )
+    }
+
+    fn diff(&self, other: &Self, options: &DiffOptions, diff: &mut ResponseDiff) {
+        if !self.has_keys_of(other) {
+            diff.push(format!(
+                "{} (tag keys [{}]) vs {} (tag keys [{}])",
+                self.name(),
+                format_tag_keys(self.tag_keys.iter()),
+                other.name(),
+                format_tag_keys(other.tag_keys.iter())
+            ));
+            return;
+        }
+
+        if self.series.len() != other.series.len() {
+            diff.push(format!(
+                "{}: different number of series: {} vs {}",
+                self.name(),
+                self.series.len(),
+                other.series.len()
+            ));
+        }
+
+        for (left, right) in self.series.iter().zip(other.series.iter()) {
+            left.diff(right, options, diff);
+        }
+    }
+}
+
+impl Series {
+    fn tag_set(&self) -> Vec<(&[u8], &[u8])> {
+        self.tags
+            .iter()
+            .map(|tag| (tag.key.as_slice(), tag.value.as_slice()))
+            .collect()
+    }
+
+    fn name(&self) -> String {
+        format!("series {}", format_tags(&self.tags))
+    }
+
+    fn diff(&self, other: &Self, options: &DiffOptions, diff: &mut ResponseDiff) {
+        if self.tags != other.tags {
+            diff.push(format!("{} vs {}", self.name(), other.name()));
+            return;
+        }
+
+        let mut left = self.points.iter().peekable();
+        let mut right = other.points.iter().peekable();
+
+        loop {
+            match (left.peek(), right.peek()) {
+                (None, None) => break,
+                (Some(l), Some(r)) if l.timestamp == r.timestamp => {
+                    match l.value.matches(&r.value, options) {
+                        ValueMatch::Exact => {}
+                        ValueMatch::WithinTolerance => diff.tolerance_matches += 1,
+                        ValueMatch::Different => diff.push(self.point_difference(l, r)),
+                    }
+                    left.next();
+                    right.next();
+                }
+                (Some(l), Some(r)) if l.timestamp < r.timestamp => {
+                    diff.push(self.missing_point("left", l));
+                    left.next();
+                }
+                (Some(l), None) => {
+                    diff.push(self.missing_point("left", l));
+                    left.next();
+                }
+                (_, Some(r)) => {
+                    diff.push(self.missing_point("right", r));
+                    right.next();
+                }
+            }
+        }
+    }
+
+    fn point_difference(&self, left: &Point, right: &Point) -> String {
+        if left.value.type_name() == right.value.type_name() {
+            format!(
+                "{}: point at ts {} differs: {} vs {}",
+                self.name(),
+                left.timestamp,
+                left.value,
+                right.value
+            )
+        } else {
+            format!(
+                "{}: point at ts {} differs: {} ({}) vs {} ({})",
+                self.name(),
+                left.timestamp,
+                left.value,
+                left.value.type_name(),
+                right.value,
+                right.value.type_name()
+            )
+        }
+    }
+
+    fn missing_point(&self, side: &str, point: &Point) -> String {
+        format!(
+            "{}: point at ts {} only in {}: {}",
+            self.name(),
+            point.timestamp,
+            side,
+            point.value
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use generated_types::influxdata::platform::storage::read_response::{
+        FloatPointsFrame, Frame, GroupFrame, SeriesFrame,
+    };
+
+    use super::*;
+
+    fn group(partition_key_val: &str) -> Data {
+        Data::Group(GroupFrame {
+            tag_keys: vec![b"host".to_vec()],
+            partition_key_vals: vec![partition_key_val.as_bytes().to_vec()],
+        })
+    }
+
+    fn series(host: &str) -> Data {
+        Data::Series(SeriesFrame {
+            tags: vec![Tag {
+                key: b"host".to_vec(),
+                value: host.as_bytes().to_vec(),
+            }],
+            ..Default::default()
+        })
+    }
+
+    fn points(timestamps: &[i64], values: &[f64]) -> Data {
+        Data::FloatPoints(FloatPointsFrame {
+            timestamps: timestamps.to_vec(),
+            values: values.to_vec(),
+        })
+    }
+
+    fn response(frames: Vec<Data>) -> Method {
+        Method::ReadResponse(ReadResponse {
+            frames: frames
+                .into_iter()
+                .map(|data| Frame { data: Some(data) })
+                .collect(),
+        })
+    }
+
+    fn string_values(values: &[&str]) -> Method {
+        Method::StringValuesResponse(StringValuesResponse {
+            values: values.iter().map(|v| v.as_bytes().to_vec()).collect(),
+        })
+    }
+
+    fn truncated(partial: Option<Method>) -> Method {
+        Method::Truncated {
+            length: 1000,
+            bytes: Bytes::from_static(b"..."),
+            partial: partial.map(Box::new),
+        }
+    }
+
+    fn differences(left: &[Method], right: &[Method], options: &DiffOptions) -> Vec<String> {
+        diff_responses(left, right, options).differences
+    }
+
+    #[test]
+    fn chunking_does_not_matter() {
+        let left = [response(vec![
+            series("a"),
+            points(&[1, 2, 3], &[1.0, 2.0, 3.0]),
+        ])];
+        let right = [
+            response(vec![series("a"), points(&[1], &[1.0])]),
+            response(vec![points(&[2, 3], &[2.0, 3.0])]),
+        ];
+
+        assert!(differences(&left, &right, &DiffOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn point_differences() {
+        let left = [response(vec![series("a"), points(&[1, 2], &[1.0, 2.0])])];
+        let right = [response(vec![series("a"), points(&[2, 3], &[2.5, 3.0])])];
+
+        assert_eq!(
+            differences(&left, &right, &DiffOptions::default()),
+            vec![
+                "series {host=\"a\"}: point at ts 1 only in left: 1.0",
+                "series {host=\"a\"}: point at ts 2 differs: 2.0 vs 2.5",
+                "series {host=\"a\"}: point at ts 3 only in right: 3.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn string_values_responses_are_merged() {
+        let left = [string_values(&["a", "b"])];
+        let right = [string_values(&["b"]), string_values(&["a"])];
+
+        assert_eq!(
+            differences(&left, &right, &DiffOptions::default()),
+            vec!["values in different order: [\"a\", \"b\"] vs [\"b\", \"a\"]"]
+        );
+
+        let right = [string_values(&["a", "c"])];
+        assert_eq!(
+            differences(&left, &right, &DiffOptions::default()),
+            vec![
+                "values only in left: [\"b\"]",
+                "values only in right: [\"c\"]"
+            ]
+        );
+    }
+
+    #[test]
+    fn ignore_order() {
+        let left = [response(vec![
+            series("a"),
+            points(&[1], &[1.0]),
+            series("b"),
+            points(&[1], &[2.0]),
+        ])];
+        let right = [response(vec![
+            series("b"),
+            points(&[1], &[2.0]),
+            series("a"),
+            points(&[1], &[1.0]),
+        ])];
+
+        assert!(!differences(&left, &right, &DiffOptions::default()).is_empty());
+
+        let options = DiffOptions {
+            ignore_order: true,
+            ..Default::default()
+        };
+        assert!(differences(&left, &right, &options).is_empty());
+    }
+
+    #[test]
+    fn strict_group_order_still_sorts_series() {
+        let left = [response(vec![
+            group("x"),
+            series("a"),
+            series("b"),
+            group("y"),
+            series("c"),
+        ])];
+        let series_reordered = [response(vec![
+            group("x"),
+            series("b"),
+            series("a"),
+            group("y"),
+            series("c"),
+        ])];
+        let groups_reordered = [response(vec![
+            group("y"),
+            series("c"),
+            group("x"),
+            series("a"),
+            series("b"),
+        ])];
+
+        let options = DiffOptions {
+            ignore_order: true,
+            strict_group_order: true,
+            ..Default::default()
+        };
+        assert!(differences(&left, &series_reordered, &options).is_empty());
+        assert!(!differences(&left, &groups_reordered, &options).is_empty());
+
+        let options = DiffOptions {
+            strict_group_order: false,
+            ..options
+        };
+        assert!(differences(&left, &groups_reordered, &options).is_empty());
+    }
+
+    #[test]
+    fn string_values_ignore_order() {
+        let left = [string_values(&["a", "b"])];
+        let right = [string_values(&["b"]), string_values(&["a"])];
+
+        let options = DiffOptions {
+            ignore_order: true,
+            ..Default::default()
+        };
+        assert!(differences(&left, &right, &options).is_empty());
+    }
+
+    #[test]
+    fn float_match() {
+        let options = DiffOptions {
+            float_abs_epsilon: 0.1,
+            float_rel_epsilon: 0.01,
+            ..Default::default()
+        };
+
+        assert_eq!(options.float_match(1.0, 1.0), ValueMatch::Exact);
+        assert_eq!(options.float_match(f64::NAN, f64::NAN), ValueMatch::Exact);
+        assert_eq!(
+            options.float_match(f64::INFINITY, f64::INFINITY),
+            ValueMatch::Exact
+        );
+        assert_eq!(options.float_match(1.0, 1.05), ValueMatch::WithinTolerance);
+        assert_eq!(
+            options.float_match(1000.0, 1005.0),
+            ValueMatch::WithinTolerance
+        );
+        assert_eq!(options.float_match(1.0, 1.2), ValueMatch::Different);
+        assert_eq!(options.float_match(f64::NAN, 1.0), ValueMatch::Different);
+        assert_eq!(
+            options.float_match(f64::INFINITY, f64::NEG_INFINITY),
+            ValueMatch::Different
+        );
+        assert_eq!(
+            options.float_match(f64::INFINITY, f64::MAX),
+            ValueMatch::Different
+        );
+
+        // an infinite relative tolerance still does not match infinities
+        let options = DiffOptions {
+            float_rel_epsilon: f64::INFINITY,
+            ..Default::default()
+        };
+        assert_eq!(options.float_match(1.0, 2.0), ValueMatch::WithinTolerance);
+        assert_eq!(
+            options.float_match(f64::INFINITY, 1.0),
+            ValueMatch::Different
+        );
+    }
+
+    #[test]
+    fn truncated_responses_are_compared_as_far_as_captured() {
+        let left = [
+            response(vec![series("a"), points(&[1, 2], &[1.0, 2.0])]),
+            response(vec![series("b"), points(&[1], &[3.0])]),
+        ];
+        let right = [
+            response(vec![series("a"), points(&[1], &[1.0])]),
+            truncated(Some(response(vec![points(&[2], &[2.0])]))),
+            response(vec![series("c")]),
+        ];
+
+        let diff = diff_responses(&left, &right, &DiffOptions::default());
+        assert!(diff.differences.is_empty(), "{:?}", diff.differences);
+        assert_eq!(
+            diff.truncated,
+            vec!["right response 2 of 3 truncated (3 of 1000 bytes)"]
+        );
+
+        // what was captured is still compared
+        let right = [
+            response(vec![series("a"), points(&[1], &[1.5])]),
+            truncated(None),
+        ];
+        let diff = diff_responses(&left, &right, &DiffOptions::default());
+        assert_eq!(
+            diff.differences,
+            vec!["series {host=\"a\"}: point at ts 1 differs: 1.0 vs 1.5"]
+        );
+    }
+
+    #[test]
+    fn truncated_string_values_are_compared_as_far_as_captured() {
+        let left = [string_values(&["a", "b"])];
+
+        let right = [string_values(&["a"]), truncated(None)];
+        assert!(differences(&left, &right, &DiffOptions::default()).is_empty());
+
+        let right = [string_values(&["c"]), truncated(None)];
+        assert_eq!(
+            differences(&left, &right, &DiffOptions::default()),
+            vec![
+                "values only in left: [\"a\"]",
+                "values only in right: [\"c\"]"
+            ]
+        );
+    }
+}
@@ -7,14 +7,22 @@ use std::{
 };
 
 use crate::{
-    calls::Calls,
+    calls::{Calls, CallsBuilder},
+    decoder::MethodRegistry,
     entries::Entries,
     error::{Error, Result},
+    methods::{format_read_source, Method},
     path::LogIterator,
+    predicate::format_predicate,
+    status::StatusDetails,
+    view::MethodView,
+    wire::format_raw,
 };
 
 pub struct DumpCalls {
     start_path: PathBuf,
+    recover: bool,
+    decoders: MethodRegistry,
 }
 
 impl DumpCalls {
@@ -22,7 +30,23 @@ impl DumpCalls {
     pub fn new(start_path: impl Into<PathBuf>) -> Self {
         let start_path = start_path.into();
 
-        Self { start_path }
+        Self {
+            start_path,
+            recover: false,
+            decoders: MethodRegistry::default(),
+        }
+    }
+
+    /// Skip over corrupted parts of the logs rather than stopping at them
+    pub fn with_recovery(mut self, recover: bool) -> Self {
+        self.recover = recover;
+        self
+    }
+
+    /// Decode requests and responses with `decoders`
+    pub fn with_decoders(mut self, decoders: MethodRegistry) -> Self {
+        self.decoders = decoders;
+        self
     }
 
     pub fn process(&mut self) -> Result<Calls> {
@@ -44,33 +68,48 @@ impl DumpCalls {
     fn process_path(&self, p: &Path) -> Result<Calls> {
         println!("Processing {:?}", p);
 
-        let entries = match Entries::try_new(p) {
-            Ok(entries) => entries,
+        let mut entries = match Entries::try_new(p) {
+            Ok(entries) => entries.with_recovery(self.recover),
             Err(e) => return Err(format!("Error reading {:?}: {}", p, e).into()),
         };
 
         let start = Instant::now();
 
-        // split them into Ok and Errors
-        let (ok_entries, err_entries): (Vec<_>, Vec<_>) = entries
-            .map(|result| match result {
-                Ok(entry) => (Some(entry), None),
-                Err(msg) => (None, Some(msg)),
-            })
-            .unzip();
-
-        let ok_entries: Vec<_> = ok_entries.into_iter().flatten().collect();
+        // build calls as the entries are read, rather than reading all
+        // the entries of the file first (the calls are all kept though)
+        let mut builder = CallsBuilder::new().with_decoders(self.decoders.clone());
+        let mut calls = vec![];
+        let (mut num_ok, mut num_err) = (0, 0);
+        for entry in entries.by_ref() {
+            match entry {
+                Ok(entry) => {
+                    num_ok += 1;
+                    calls.extend(builder.push(entry));
+                }
+                Err(_) => num_err += 1,
+            }
+        }
 
         println!(
             "Read {} ok entries and {} err entries in {:?}",
-            ok_entries.len(),
-            err_entries.into_iter().flatten().count(),
+            num_ok,
+            num_err,
             Instant::now() - start
         );
+        if let Some(report) = entries.recovery_report() {
+            println!("Recovered {:?}: {}", p, report);
+        }
+
+        let num_incomplete = builder.in_flight();
+        calls.extend(builder.finish());
+        calls.sort_by_key(|call| (call.logged_by, call.id));
 
-        // collect into calls
-        let calls: Calls = ok_entries.into_iter().collect();
-        println!("Found {} calls", calls.len());
+        let calls = Calls::from(calls);
+        println!(
+            "Found {} calls ({} incomplete)",
+            calls.len(),
+            num_incomplete
+        );
 
         Ok(calls)
     }
@@ -81,6 +120,24 @@ impl DumpCalls {
     {
         for call in calls.iter() {
             writeln!(out, "{}", call)?;
+            if let Some(read_source) = call.request.as_ref().and_then(Method::read_source) {
+                writeln!(out, "  read source: {}", format_read_source(&read_source))?;
+            }
+            if let Some(predicate) = call.request.as_ref().and_then(Method::predicate) {
+                writeln!(out, "  predicate: {}", format_predicate(predicate))?;
+            }
+            if let Some(status_details) = &call.status_details {
+                writeln!(out, "  status details: {}", status_details)?;
+                if let StatusDetails::Raw { bytes, .. } = status_details {
+                    for line in format_raw(bytes).lines() {
+                        writeln!(out, "    {}", line)?;
+                    }
+                }
+            }
+            writeln!(out, "  lifecycle: {}", call.lifecycle)?;
+            for anomaly in &call.anomalies {
+                writeln!(out, "  anomaly: {}", anomaly)?;
+            }
         }
 
         // full debug dump
@@ -104,17 +161,134 @@ impl DumpCalls {
 
             if rpc_method {
                 writeln!(out, "Non storage offset call:\n  {}", call)?;
-                writeln!(out, "  request: {:?}", call.request)?;
-                writeln!(out, "  response: {:?}", call.responses)?;
+                match &call.request {
+                    Some(request) => writeln!(out, "  request: {}", MethodView(request))?,
+                    None => writeln!(out, "  request: <none>")?,
+                }
+                for response in &call.responses {
+                    writeln!(out, "  response: {}", MethodView(response))?;
+                }
             }
         }
 
         Ok(())
     }
 
-    pub fn write_calls_binary(&self, calls: Calls, path: &str) -> Result<()> {
+    /// Writes `calls` to `path`, to be read back with [`Self::read_calls_binary`]
+    pub fn write_calls_binary(calls: Calls, path: &Path) -> Result<()> {
         use std::fs::File;
         let contents = File::create(path).map_err(|e| Error::from(e.to_string()))?;
         bincode::serialize_into(contents, &calls).map_err(|e| Error::from(e.to_string()))
     }
+
+    /// Reads back [`Calls`] previously written by [`Self::write_calls_binary`]
+    pub fn read_calls_binary(path: &Path) -> Result<Calls> {
+        use std::fs::File;
+        let contents = File::open(path).map_err(|e| Error::from(e.to_string()))?;
+        bincode::deserialize_from(contents).map_err(|e| Error::from(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use generated_types::{
+        google::{
+            protobuf::Any,
+            rpc::{ErrorInfo, Status},
+        },
+        influxdata::platform::storage::{
+            read_response::{frame::Data, FloatPointsFrame, Frame, SeriesFrame},
+            ReadFilterRequest, ReadResponse, ReadSource, Tag, TimestampRange,
+        },
+    };
+    use prost::Message;
+
+    use super::*;
+    use crate::{call::Call, methods::READ_SOURCE_TYPE_URL};
+
+    #[test]
+    fn binary_round_trip() {
+        // default values (e.g. `partition_id: 0`, `start: 0`, `0.0`) included
+        let source = ReadSource {
+            org_id: 0xabcd,
+            bucket_id: 0x1234,
+            partition_id: 0,
+        };
+        let request = ReadFilterRequest {
+            read_source: Some(Any {
+                type_url: READ_SOURCE_TYPE_URL.to_string(),
+                value: source.encode_to_vec().into(),
+            }),
+            range: Some(TimestampRange {
+                start: 0,
+                end: 1_000,
+            }),
+            ..Default::default()
+        };
+        let response = ReadResponse {
+            frames: vec![
+                Frame {
+                    data: Some(Data::Series(SeriesFrame {
+                        tags: vec![Tag {
+                            key: b"host".to_vec(),
+                            value: b"a".to_vec(),
+                        }],
+                        ..Default::default()
+                    })),
+                },
+                Frame {
+                    data: Some(Data::FloatPoints(FloatPointsFrame {
+                        timestamps: vec![0, 1],
+                        values: vec![0.0, 1.5],
+                    })),
+                },
+            ],
+        };
+        let status = Status {
+            code: 5,
+            message: "not found".to_string(),
+            details: vec![Any {
+                type_url: "type.googleapis.com/google.rpc.ErrorInfo".to_string(),
+                value: ErrorInfo {
+                    reason: "BUCKET_NOT_FOUND".to_string(),
+                    ..Default::default()
+                }
+                .encode_to_vec()
+                .into(),
+            }],
+        };
+
+        let decoders = MethodRegistry::default();
+        let mut call = Call::new(1);
+        call.with_method_name("/influxdata.platform.storage.Storage/ReadFilter".to_string())
+            .with_request_data(request.encode_to_vec(), &decoders)
+            .with_response_data(response.encode_to_vec(), &decoders)
+            .with_status_code(5)
+            .with_status_details(status.encode_to_vec());
+        assert_eq!(call.request, Some(Method::ReadFilterRequest(request)));
+        assert_eq!(call.responses, vec![Method::ReadResponse(response)]);
+        assert!(matches!(
+            call.status_details,
+            Some(StatusDetails::Status { .. })
+        ));
+
+        let path = std::env::temp_dir().join(format!(
+            "influxrpc_compare_round_trip_{}.bin",
+            std::process::id()
+        ));
+        DumpCalls::write_calls_binary(Calls::from(vec![call.clone()]), &path).unwrap();
+        let read = DumpCalls::read_calls_binary(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let read = read.unwrap();
+        assert_eq!(read.len(), 1);
+        let read = read.iter().next().unwrap();
+        assert_eq!(read.method_name, call.method_name);
+        assert_eq!(read.request, call.request);
+        assert_eq!(read.request_data, call.request_data);
+        assert_eq!(read.responses, call.responses);
+        assert_eq!(read.response_data, call.response_data);
+        assert_eq!(read.status_code, call.status_code);
+        assert_eq!(read.status_details, call.status_details);
+    }
 }
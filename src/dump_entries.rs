@@ -9,6 +9,7 @@ use crate::{entries::Entries, error::Result, path::LogIterator};
 
 pub struct DumpEntries {
     start_path: PathBuf,
+    recover: bool,
 }
 
 impl DumpEntries {
@@ -16,7 +17,16 @@ impl DumpEntries {
     pub fn new(start_path: impl Into<PathBuf>) -> Self {
         let start_path = start_path.into();
 
-        Self { start_path }
+        Self {
+            start_path,
+            recover: false,
+        }
+    }
+
+    /// Skip over corrupted parts of the logs rather than stopping at them
+    pub fn with_recovery(mut self, recover: bool) -> Self {
+        self.recover = recover;
+        self
     }
 
     pub fn dump<W: Write>(&mut self, out: &mut W) -> Result<()> {
@@ -37,8 +47,8 @@ impl DumpEntries {
     pub fn dump_path<W: Write>(&self, out: &mut W, p: &Path) -> Result<()> {
         //println!("path: {:?}", p);
         println!("Attempting to dump {:?}", p);
-        let entries = match Entries::try_new(p) {
-            Ok(entries) => entries,
+        let mut entries = match Entries::try_new(p) {
+            Ok(entries) => entries.with_recovery(self.recover),
             Err(e) => {
                 writeln!(out, "Error reading {:?}: {}", p, e)?;
                 return Ok(());
@@ -46,7 +56,7 @@ impl DumpEntries {
         };
 
         let mut num_entries = 0;
-        entries.enumerate().try_for_each(|(i, entry)| {
+        entries.by_ref().enumerate().try_for_each(|(i, entry)| {
             num_entries += 1;
             match entry {
                 Ok(entry) => {
@@ -57,6 +67,9 @@ impl DumpEntries {
         })?;
 
         println!("Dumped {} entries", num_entries);
+        if let Some(report) = entries.recovery_report() {
+            println!("Recovered {:?}: {}", p, report);
+        }
         Ok(())
     }
 }
@@ -1,45 +1,239 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::File,
+    io::{BufReader, Read},
+    ops::Range,
+    path::Path,
+};
 
-use bytes::Bytes;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
-use crate::{entry::Entry, error::Result};
+use crate::{
+    entry::{Entry, Logger},
+    error::{Error, Result},
+};
 
-/// decode Raw GrpcLogEntry from files
+// largest record that is read, as a corrupt length prefix tends to be
+// huge, and would otherwise have the rest of the log read into memory
+const MAX_RECORD_LEN: usize = 64 * 1024 * 1024;
+
+// how much to read from the underlying reader at a time
+const READ_CHUNK_LEN: usize = 64 * 1024;
+
+/// decode Raw GrpcLogEntry from files, incrementally
 ///
 /// To use:
-/// ```
+/// ```ignore
 /// let entries = Entries::try_from(file)?;
 /// for entry in entries {
 ///   // Do some awesome stuff
 /// }
 /// ```
-pub struct Entries {
-    records: LengthDelimitedRecords,
+pub struct Entries<R = BufReader<File>> {
+    records: LengthDelimitedRecords<R>,
+    /// no more entries will be returned
+    done: bool,
+    /// Set when recovering from corruption (see [`Self::with_recovery`])
+    recovery: Option<Recovery>,
+}
+
+/// What was lost while reading a log in recovery mode
+#[derive(Debug, Default, Clone)]
+pub struct RecoveryReport {
+    /// Byte ranges skipped because they could not be read as records,
+    /// or held records that could not be decoded
+    pub skipped: Vec<Range<u64>>,
+
+    /// Records that were skipped as they could not be decoded
+    pub undecodable_records: u64,
+
+    /// Entries missing from calls, based on gaps in their sequence ids.
+    /// Entries at the start or end of a call (or of a whole call) that
+    /// were lost can not be detected, so this is a lower bound.
+    pub missing_entries: u64,
+}
+
+impl RecoveryReport {
+    pub fn bytes_lost(&self) -> u64 {
+        self.skipped
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum()
+    }
+}
+
+impl Display for RecoveryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "lost {} bytes in {} skipped ranges ({} undecodable records), at least {} entries missing",
+            self.bytes_lost(),
+            self.skipped.len(),
+            self.undecodable_records,
+            self.missing_entries
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Recovery {
+    report: RecoveryReport,
+    /// last sequence id seen for each call that has not ended yet, by
+    /// the side that logged it and call id
+    sequence_ids: HashMap<(Logger, u64), u64>,
+}
+
+impl Recovery {
+    /// Counts the entries missing between `entry` and the previous
+    /// entry of its call. Calls are forgotten once they end, so only the
+    /// calls in flight are tracked.
+    fn track(&mut self, entry: &pbbinarylog::GrpcLogEntry) {
+        use pbbinarylog::grpc_log_entry::EventType;
+
+        let key = (Logger::from(entry.logger()), entry.call_id);
+        let sequence_id = entry.sequence_id_within_call;
+        let last = match EventType::from_i32(entry.r#type) {
+            Some(EventType::ServerTrailer | EventType::Cancel) => self.sequence_ids.remove(&key),
+            _ => self.sequence_ids.insert(key, sequence_id),
+        };
+
+        // calls already in progress when the log starts (e.g. after the
+        // log was rotated) did not lose the entries before their first one
+        if let Some(last) = last {
+            self.report.missing_entries += sequence_id.saturating_sub(last + 1);
+        }
+    }
 }
 
 impl Entries {
     pub fn try_new(p: impl AsRef<Path>) -> Result<Self> {
-        // attempt to read the file
-        let contents = std::fs::read(p.as_ref())?;
+        // attempt to open the file
+        let file = File::open(p.as_ref())?;
+        println!("Reading entries from {:?}", p.as_ref());
 
-        let bytes: Bytes = contents.into();
-        println!("Read {} bytes from {:?}", bytes.len(), p.as_ref());
+        Ok(Self::from_reader(BufReader::new(file)))
+    }
+}
 
-        Ok(Self {
-            records: LengthDelimitedRecords::new(bytes),
-        })
+impl<R: Read> Entries<R> {
+    /// Reads entries from `reader` as they are needed, so the whole
+    /// log never has to be held in memory
+    pub fn from_reader(reader: R) -> Self {
+        Self {
+            records: LengthDelimitedRecords::new(reader),
+            done: false,
+            recovery: None,
+        }
+    }
+
+    /// If set, rather than stopping at the first record that can not be
+    /// read (e.g. a corrupt length, or a log cut off mid-write), skip
+    /// forward to the next plausible entry and continue from there
+    pub fn with_recovery(mut self, recover: bool) -> Self {
+        self.recovery = recover.then(Recovery::default);
+        self
+    }
+
+    /// What was lost so far, if in recovery mode
+    pub fn recovery_report(&self) -> Option<&RecoveryReport> {
+        self.recovery.as_ref().map(|recovery| &recovery.report)
+    }
+
+    /// Skips the corruption found at `offset`, returning whether there
+    /// is a plausible entry to continue with
+    fn recover(&mut self, offset: u64, e: Error) -> Result<bool> {
+        let found = self.records.resync(is_plausible_entry)?;
+
+        let skipped = offset..self.records.offset();
+        println!(
+            "Skipped {} bytes at offset {}..{} after: {}",
+            skipped.end - skipped.start,
+            skipped.start,
+            skipped.end,
+            e
+        );
+        if let Some(recovery) = self.recovery.as_mut() {
+            recovery.report.skipped.push(skipped);
+        }
+
+        Ok(found)
     }
 }
 
-impl Iterator for Entries {
+impl<R: Read> Iterator for Entries<R> {
     type Item = Result<Entry>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.records.next().map(|bytes_result| {
-            bytes_result
-                .and_then(|bytes| pbbinarylog::decode_log_entry(bytes).map_err(|e| e.into()))
-                .map(Entry::new)
-        })
+        while !self.done {
+            let offset = self.records.offset();
+            let record = match self.records.next_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) if self.recovery.is_some() => {
+                    match self.recover(offset, e) {
+                        Ok(found) => self.done = !found,
+                        Err(e) => {
+                            self.done = true;
+                            return Some(Err(e));
+                        }
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+
+            let entry = match pbbinarylog::decode_log_entry(record) {
+                Ok(entry) => entry,
+                Err(e) => match self.recovery.as_mut() {
+                    Some(recovery) => {
+                        let skipped = offset..self.records.offset();
+                        println!(
+                            "Skipped undecodable record at offset {}..{}: {}",
+                            skipped.start, skipped.end, e
+                        );
+                        recovery.report.skipped.push(skipped);
+                        recovery.report.undecodable_records += 1;
+                        continue;
+                    }
+                    None => return Some(Err(e.into())),
+                },
+            };
+
+            if let Some(recovery) = self.recovery.as_mut() {
+                recovery.track(&entry);
+            }
+
+            return Some(Ok(Entry::new(entry)));
+        }
+
+        None
+    }
+}
+
+/// Does `bytes` look like a real log entry, rather than random bytes
+/// that happen to decode as one?
+fn is_plausible_entry(bytes: &[u8]) -> bool {
+    use pbbinarylog::grpc_log_entry::EventType;
+    use prost::Message;
+
+    match pbbinarylog::GrpcLogEntry::decode(bytes) {
+        Ok(entry) => {
+            entry.call_id != 0
+                && entry.sequence_id_within_call != 0
+                && entry.timestamp.is_some()
+                && matches!(
+                    EventType::from_i32(entry.r#type),
+                    Some(event_type) if event_type != EventType::Unknown
+                )
+        }
+        Err(_) => false,
     }
 }
 
@@ -50,72 +244,310 @@ impl Iterator for Entries {
 /// (i32 length)(... data ...)
 /// (i32 length)(... data ...)
 /// ```
-struct LengthDelimitedRecords {
-    bytes: Bytes,
-    current_offset: Option<usize>,
+struct LengthDelimitedRecords<R> {
+    reader: R,
+    /// bytes read but not yet consumed
+    buf: BytesMut,
+    /// offset of `buf` from the start of the input
+    offset: u64,
+    /// the reader has no more bytes
+    eof: bool,
 }
 
-impl LengthDelimitedRecords {
-    pub fn new(bytes: Bytes) -> Self {
+impl<R: Read> LengthDelimitedRecords<R> {
+    pub fn new(reader: R) -> Self {
         Self {
-            bytes,
-            current_offset: Some(0),
+            reader,
+            buf: BytesMut::new(),
+            offset: 0,
+            eof: false,
         }
     }
-}
-
-impl Iterator for LengthDelimitedRecords {
-    type Item = Result<Bytes>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let current_offset = self.current_offset.take()?;
+    /// Offset of the next record from the start of the input
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
 
+    /// Returns the next record, or None at the end of the input. On
+    /// error, nothing is consumed.
+    pub fn next_record(&mut self) -> Result<Option<Bytes>> {
         // try and read the next 4 bytes as a length
-        let remain = self.bytes.len() - current_offset;
-        //println!("current_offset is {}, {} bytes remain", current_offset, remain);
-
-        if remain == 0 {
-            // no more records!
-            return None;
+        if !self.fill(4)? {
+            if self.buf.is_empty() {
+                // no more records!
+                return Ok(None);
+            }
+            return Err(format!(
+                "Can not read next length from offset {}, only {} bytes remain",
+                self.offset,
+                self.buf.len()
+            )
+            .into());
         }
 
-        let len_bytes =
-            if let Ok(len_bytes) = self.bytes[current_offset..current_offset + 4].try_into() {
-                len_bytes
-            } else {
-                return Some(Err(format!(
-                    "Can not read next length from offset {}, only {} bytes remain",
-                    current_offset, remain
-                )
-                .into()));
-            };
+        let record_len = self.record_len();
+        if record_len > MAX_RECORD_LEN {
+            return Err(format!(
+                "Reported record length of {} at offset {} is larger than the maximum of {}",
+                record_len, self.offset, MAX_RECORD_LEN
+            )
+            .into());
+        }
+        if !self.fill(4 + record_len)? {
+            return Err(format!(
+                "Reported record length of {} at offset {} is larger than {} remaining bytes",
+                record_len,
+                self.offset,
+                self.buf.len() - 4
+            )
+            .into());
+        }
 
-        // account for the length field
-        let current_offset = current_offset + 4;
+        self.advance(4);
+        let record = self.buf.split_to(record_len).freeze();
+        self.offset += record_len as u64;
+        //println!("Next offset: {:?}", self.offset);
+        Ok(Some(record))
+    }
 
-        let record_len = i32::from_be_bytes(len_bytes) as usize;
+    /// Skips forward (at least one byte) to the next offset that holds a
+    /// record accepted by `accept`. Returns false if the end of the input
+    /// was reached without finding one.
+    pub fn resync(&mut self, accept: impl Fn(&[u8]) -> bool) -> Result<bool> {
+        loop {
+            self.advance(1);
+            if !self.fill(1)? {
+                return Ok(false);
+            }
+            if !self.fill(4)? {
+                continue;
+            }
 
-        if record_len > remain {
-            return Some(Err(format!(
-                "Reported record length of {} is larger than {} remaining bytes",
-                record_len, remain
-            )
-            .into()));
+            let record_len = self.record_len();
+            if record_len > MAX_RECORD_LEN || !self.fill(4 + record_len)? {
+                continue;
+            }
+            if accept(&self.buf[4..4 + record_len]) {
+                return Ok(true);
+            }
         }
+    }
 
-        let next_offset = current_offset + record_len;
-        if next_offset > (self.bytes.len() - 1) {
-            return Some(Err(format!(
-                "Next offset {} is past end of file {}",
-                next_offset,
-                self.bytes.len() - 1
-            )
-            .into()));
+    /// the length prefix at the current offset
+    fn record_len(&self) -> usize {
+        (&self.buf[..4]).get_u32() as usize
+    }
+
+    fn advance(&mut self, n: usize) {
+        let n = n.min(self.buf.len());
+        self.buf.advance(n);
+        self.offset += n as u64;
+    }
+
+    /// Reads until at least `n` bytes are buffered, returning false if
+    /// the input ends first
+    fn fill(&mut self, n: usize) -> Result<bool> {
+        while self.buf.len() < n && !self.eof {
+            let wanted = (n - self.buf.len()).max(READ_CHUNK_LEN);
+            let read = std::io::copy(
+                &mut (&mut self.reader).take(wanted as u64),
+                &mut (&mut self.buf).writer(),
+            )?;
+            self.eof = read == 0;
         }
+        Ok(self.buf.len() >= n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pbbinarylog::grpc_log_entry::{EventType, Logger as PbLogger};
+    use prost::Message;
+
+    use super::*;
+
+    fn record(call_id: u64, sequence_id: u64, event_type: EventType) -> Vec<u8> {
+        logged(PbLogger::Client, call_id, sequence_id, event_type)
+    }
+
+    fn logged(logger: PbLogger, call_id: u64, sequence_id: u64, event_type: EventType) -> Vec<u8> {
+        let entry = pbbinarylog::GrpcLogEntry {
+            timestamp: Some(prost_types::Timestamp {
+                seconds: 1_644_522_055,
+                nanos: 0,
+            }),
+            call_id,
+            sequence_id_within_call: sequence_id,
+            r#type: event_type as i32,
+            logger: logger as i32,
+            ..Default::default()
+        };
+        length_prefixed(&entry.encode_to_vec())
+    }
+
+    fn length_prefixed(bytes: &[u8]) -> Vec<u8> {
+        let mut record = (bytes.len() as u32).to_be_bytes().to_vec();
+        record.extend_from_slice(bytes);
+        record
+    }
+
+    fn read(log: Vec<u8>, recover: bool) -> (Vec<Result<Entry>>, Option<RecoveryReport>) {
+        let mut entries = Entries::from_reader(log.as_slice()).with_recovery(recover);
+        let read = entries.by_ref().collect();
+        (read, entries.recovery_report().cloned())
+    }
+
+    fn call_ids(entries: &[Result<Entry>]) -> Vec<u64> {
+        entries
+            .iter()
+            .map(|entry| entry.as_ref().unwrap().call_id)
+            .collect()
+    }
+
+    #[test]
+    fn reads_all_entries() {
+        let log = [
+            record(1, 1, EventType::ClientHeader),
+            record(2, 1, EventType::ClientHeader),
+            record(1, 2, EventType::ServerTrailer),
+        ]
+        .concat();
+
+        let (entries, report) = read(log, false);
+        assert_eq!(call_ids(&entries), vec![1, 2, 1]);
+        assert!(report.is_none());
+    }
+
+    #[test]
+    fn stops_at_corruption_without_recovery() {
+        let log = [
+            record(1, 1, EventType::ClientHeader),
+            vec![0xde, 0xad, 0xbe, 0xef, 0x01],
+            record(1, 2, EventType::ServerTrailer),
+        ]
+        .concat();
+
+        let (entries, _) = read(log, false);
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_ok());
+        assert!(entries[1].is_err());
+    }
+
+    #[test]
+    fn skips_garbage_with_recovery() {
+        let first = record(1, 1, EventType::ClientHeader);
+        let garbage = vec![0xde, 0xad, 0xbe, 0xef, 0x01];
+        let log = [
+            first.clone(),
+            garbage.clone(),
+            record(1, 2, EventType::ServerTrailer),
+        ]
+        .concat();
+
+        let (entries, report) = read(log, true);
+        assert_eq!(call_ids(&entries), vec![1, 1]);
+
+        let report = report.unwrap();
+        let start = first.len() as u64;
+        assert_eq!(report.skipped, vec![start..start + garbage.len() as u64]);
+        assert_eq!(report.bytes_lost(), garbage.len() as u64);
+        assert_eq!(report.undecodable_records, 0);
+        assert_eq!(report.missing_entries, 0);
+    }
+
+    #[test]
+    fn skips_huge_length_with_recovery() {
+        // would be read into memory in full, were it not capped
+        let huge = (MAX_RECORD_LEN as u32 + 1).to_be_bytes().to_vec();
+        let log = [huge, record(1, 1, EventType::ClientHeader)].concat();
+
+        let (entries, report) = read(log, true);
+        assert_eq!(call_ids(&entries), vec![1]);
+        assert_eq!(report.unwrap().bytes_lost(), 4);
+    }
+
+    #[test]
+    fn counts_undecodable_records() {
+        let log = [
+            record(1, 1, EventType::ClientHeader),
+            length_prefixed(&[0xff, 0xff, 0xff]),
+            record(1, 2, EventType::ServerTrailer),
+        ]
+        .concat();
+
+        let (entries, report) = read(log, true);
+        assert_eq!(call_ids(&entries), vec![1, 1]);
+
+        let report = report.unwrap();
+        assert_eq!(report.undecodable_records, 1);
+        assert_eq!(report.bytes_lost(), 7);
+    }
+
+    #[test]
+    fn counts_missing_entries() {
+        let log = [
+            record(1, 1, EventType::ClientHeader),
+            record(1, 4, EventType::ServerMessage),
+            record(1, 5, EventType::ServerTrailer),
+            // call 1 ended, so is not tracked any more
+            record(1, 1, EventType::ClientHeader),
+            record(1, 3, EventType::ClientMessage),
+        ]
+        .concat();
+
+        let (entries, report) = read(log, true);
+        assert_eq!(entries.len(), 5);
+        assert_eq!(report.unwrap().missing_entries, 3);
+    }
+
+    #[test]
+    fn calls_in_progress_at_the_start_are_not_missing_entries() {
+        // e.g. the log was rotated while the calls were in flight
+        let log = [
+            record(1, 4, EventType::ServerMessage),
+            record(1, 5, EventType::ServerTrailer),
+            record(2, 2, EventType::ClientMessage),
+            record(2, 3, EventType::ClientHalfClose),
+        ]
+        .concat();
+
+        let (entries, report) = read(log, true);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(report.unwrap().missing_entries, 0);
+    }
+
+    #[test]
+    fn client_and_server_calls_are_tracked_separately() {
+        let log = [
+            logged(PbLogger::Client, 1, 1, EventType::ClientHeader),
+            logged(PbLogger::Client, 1, 2, EventType::ClientMessage),
+            logged(PbLogger::Server, 1, 1, EventType::ClientHeader),
+            logged(PbLogger::Client, 1, 3, EventType::ClientHalfClose),
+            logged(PbLogger::Server, 1, 4, EventType::ServerMessage),
+        ]
+        .concat();
+
+        let (entries, report) = read(log, true);
+        assert_eq!(entries.len(), 5);
+        assert_eq!(report.unwrap().missing_entries, 2);
+    }
+
+    #[test]
+    fn truncated_record_at_end() {
+        let mut log = [
+            record(1, 1, EventType::ClientHeader),
+            record(1, 2, EventType::ServerTrailer),
+        ]
+        .concat();
+        log.truncate(log.len() - 2);
+
+        let (entries, _) = read(log.clone(), false);
+        assert_eq!(entries.len(), 2);
+        assert!(entries[1].is_err());
 
-        let record = self.bytes.slice(current_offset..next_offset);
-        self.current_offset = Some(next_offset);
-        //println!("Next offset: {:?}", self.current_offset);
-        Some(Ok(record))
+        let (entries, report) = read(log, true);
+        assert_eq!(call_ids(&entries), vec![1]);
+        assert_eq!(report.unwrap().skipped.len(), 1);
     }
 }
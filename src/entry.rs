@@ -2,6 +2,7 @@ use std::{collections::HashMap, fmt::Debug};
 
 use chrono::{DateTime, Duration, Utc};
 use pbbinarylog::{to_chrono_duration, to_chrono_timestamp};
+use serde::{Deserialize, Serialize};
 
 /// Native rust version of decoded [pbbinarylog::GrpcLogEntry] to make
 /// it easier to work (all types are explicit here, not just in IDEs!)
@@ -31,19 +32,30 @@ pub enum EventType {
     Cancel,
 }
 
-#[derive(Debug, Clone)]
+/// Which side of a call wrote the log entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Logger {
     Unknown,
     Client,
     Server,
 }
 
+// `#[default]` on enum variants needs rust 1.62
+#[allow(clippy::derivable_impls)]
+impl Default for Logger {
+    fn default() -> Self {
+        Self::Unknown
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Payload {
     ClientHeader(ClientHeader),
     ServerHeader(ServerHeader),
     Message(Message),
     Trailer(Trailer),
+    /// Events that carry no payload (`ClientHalfClose` and `Cancel`)
+    Empty,
 }
 
 #[derive(Debug, Clone)]
@@ -112,7 +124,7 @@ impl Entry {
             logger: logger.into(),
             payload_truncated,
             peer,
-            payload: payload.expect("Missing payload").into(),
+            payload: payload.map(Payload::from).unwrap_or(Payload::Empty),
         }
     }
 }
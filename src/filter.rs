@@ -0,0 +1,512 @@
+//! A small expression language for selecting calls, e.g.
+//!
+//! ```text
+//! method = TagValues and org = 000000000000abcd and duration > 200ms and status != 0
+//! ```
+//!
+//! Comparisons are combined with `and`, `or`, `not` and parentheses.
+//! Fields that can be compared:
+//!
+//! * `method`: gRPC method name, either in full or just the last part
+//! * `org`, `bucket`: hex ids from the headers or `ReadSource`, compared as
+//!   numbers (so leading zeros can be left out)
+//! * `status`: status code
+//! * `duration`: time between the first and last entry of the call,
+//!   with a unit of `ns`, `us`, `ms` (the default), `s` or `m`
+//! * `id`: call id
+//! * `peer`, `authority`
+//! * `header.<name>`: client header
+//!
+//! with `=`, `!=`, `<`, `<=`, `>`, `>=` or `~` (contains, for text).
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    call::Call,
+    methods::{format_id, Method},
+};
+
+/// A parsed filter expression
+#[derive(Debug, Clone)]
+pub struct Filter {
+    text: String,
+    expr: Expr,
+}
+
+impl Filter {
+    /// Does `call` satisfy the filter?
+    pub fn matches(&self, call: &Call) -> bool {
+        self.expr.matches(call)
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {} in filter {:?}", token, s));
+        }
+
+        Ok(Self {
+            text: s.to_string(),
+            expr,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Text {
+        field: TextField,
+        op: Op,
+        value: String,
+    },
+    Number {
+        field: NumberField,
+        op: Op,
+        value: i64,
+    },
+}
+
+impl Expr {
+    fn matches(&self, call: &Call) -> bool {
+        match self {
+            Self::And(left, right) => left.matches(call) && right.matches(call),
+            Self::Or(left, right) => left.matches(call) || right.matches(call),
+            Self::Not(expr) => !expr.matches(call),
+            Self::Text { field, op, value } => {
+                let actual = field.value(call);
+                match (op, actual) {
+                    (Op::NotEq, actual) => !field.equals(actual.as_deref(), value),
+                    (Op::Eq, actual) => field.equals(actual.as_deref(), value),
+                    (Op::Contains, Some(actual)) => actual.contains(value.as_str()),
+                    _ => false,
+                }
+            }
+            Self::Number { field, op, value } => match (op, field.value(call)) {
+                (Op::NotEq, actual) => actual != Some(*value),
+                (Op::Eq, Some(actual)) => actual == *value,
+                (Op::Lt, Some(actual)) => actual < *value,
+                (Op::LtEq, Some(actual)) => actual <= *value,
+                (Op::Gt, Some(actual)) => actual > *value,
+                (Op::GtEq, Some(actual)) => actual >= *value,
+                _ => false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Contains,
+}
+
+#[derive(Debug, Clone)]
+enum TextField {
+    Method,
+    Org,
+    Bucket,
+    Peer,
+    Authority,
+    Header(String),
+}
+
+impl TextField {
+    fn value(&self, call: &Call) -> Option<String> {
+        match self {
+            Self::Method => call.method_name.clone(),
+            Self::Org => call.org_id(),
+            Self::Bucket => {
+                let read_source = call.request.as_ref().and_then(Method::read_source)?;
+                Some(format_id(read_source.bucket_id))
+            }
+            Self::Peer => call.peer.clone(),
+            Self::Authority => call.authority.clone(),
+            Self::Header(name) => call.client_headers.get(name).cloned(),
+        }
+    }
+
+    fn equals(&self, actual: Option<&str>, value: &str) -> bool {
+        match (self, actual) {
+            // `TagValues` matches `/influxdata.platform.storage.Storage/TagValues`
+            (Self::Method, Some(actual)) => {
+                actual == value || actual.rsplit('/').next() == Some(value)
+            }
+            // ids are hex numbers, so `abcd` matches `000000000000abcd`
+            (Self::Org | Self::Bucket, Some(actual)) => {
+                let parse_id = |id: &str| u64::from_str_radix(id, 16).ok();
+                match (parse_id(actual), parse_id(value)) {
+                    (Some(actual), Some(value)) => actual == value,
+                    _ => actual == value,
+                }
+            }
+            (_, Some(actual)) => actual == value,
+            (_, None) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum NumberField {
+    Status,
+    /// in nanoseconds
+    Duration,
+    Id,
+}
+
+impl NumberField {
+    fn value(&self, call: &Call) -> Option<i64> {
+        match self {
+            Self::Status => call.status_code.map(i64::from),
+            Self::Duration => (call.end_time? - call.start_time?).num_nanoseconds(),
+            Self::Id => i64::try_from(call.id).ok(),
+        }
+    }
+
+    fn parse_value(&self, value: &str) -> Result<i64, String> {
+        match self {
+            Self::Status | Self::Id => value
+                .parse()
+                .map_err(|e| format!("Invalid number {:?}: {}", value, e)),
+            Self::Duration => parse_duration_ns(value),
+        }
+    }
+}
+
+/// Parses e.g. `200ms`, `1.5s` or `200` (milliseconds) as nanoseconds
+fn parse_duration_ns(value: &str) -> Result<i64, String> {
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let scale = match unit {
+        "ns" => 1.0,
+        "us" => 1e3,
+        "" | "ms" => 1e6,
+        "s" => 1e9,
+        "m" => 60e9,
+        _ => return Err(format!("Unknown duration unit {:?} in {:?}", unit, value)),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|e| format!("Invalid duration {:?}: {}", value, e))?;
+
+    Ok((number * scale) as i64)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Word(word) => write!(f, "{:?}", word),
+            Self::Quoted(text) => write!(f, "{:?}", text),
+            Self::Op(op) => write!(f, "operator {:?}", op),
+            Self::Open => write!(f, "'('"),
+            Self::Close => write!(f, "')'"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' => Token::Op(Op::Eq),
+            '~' => Token::Op(Op::Contains),
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::NotEq),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::LtEq),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::GtEq),
+            '>' => Token::Op(Op::Gt),
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(next) => text.push(next),
+                        None => return Err(format!("Unterminated string in filter {:?}", s)),
+                    }
+                }
+                Token::Quoted(text)
+            }
+            c if is_word_char(c) => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.next_if(|c| is_word_char(*c)) {
+                    word.push(next);
+                }
+                Token::Word(word)
+            }
+            _ => return Err(format!("Unexpected {:?} in filter {:?}", c, s)),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | ':')
+}
+
+/// Recursive descent parser, with `not` binding tighter than `and`,
+/// which binds tighter than `or`
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consumes the next token if it is the keyword `keyword`
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let expr = self.parse_or()?;
+            return match self.next() {
+                Some(Token::Close) => Ok(expr),
+                Some(token) => Err(format!("Expected ')', found {}", token)),
+                None => Err("Expected ')', found end of filter".to_string()),
+            };
+        }
+
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let field = match self.next() {
+            Some(Token::Word(field)) => field,
+            Some(token) => return Err(format!("Expected field name, found {}", token)),
+            None => return Err("Expected field name, found end of filter".to_string()),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            Some(token) => return Err(format!("Expected operator, found {}", token)),
+            None => return Err(format!("Expected operator after {}", field)),
+        };
+        let value = match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+            Some(token) => return Err(format!("Expected value, found {}", token)),
+            None => return Err(format!("Expected value after {} {:?}", field, op)),
+        };
+
+        let text_field = match field.to_lowercase().as_str() {
+            "method" => Some(TextField::Method),
+            "org" => Some(TextField::Org),
+            "bucket" => Some(TextField::Bucket),
+            "peer" => Some(TextField::Peer),
+            "authority" => Some(TextField::Authority),
+            name => name
+                .strip_prefix("header.")
+                .map(|name| TextField::Header(name.to_string())),
+        };
+        if let Some(field) = text_field {
+            if !matches!(op, Op::Eq | Op::NotEq | Op::Contains) {
+                return Err(format!(
+                    "Operator {:?} can not be used with {:?}",
+                    op, field
+                ));
+            }
+            return Ok(Expr::Text { field, op, value });
+        }
+
+        let field = match field.to_lowercase().as_str() {
+            "status" => NumberField::Status,
+            "duration" => NumberField::Duration,
+            "id" => NumberField::Id,
+            _ => return Err(format!("Unknown field {:?}", field)),
+        };
+        if op == Op::Contains {
+            return Err(format!(
+                "Operator {:?} can not be used with {:?}",
+                op, field
+            ));
+        }
+        let value = field.parse_value(&value)?;
+
+        Ok(Expr::Number { field, op, value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    fn call() -> Call {
+        let start = Utc.timestamp(1_644_522_055, 0);
+        let mut call = Call::new(42);
+        call.with_method_name("/influxdata.platform.storage.Storage/TagValues".to_string())
+            .with_client_headers(HashMap::from([(
+                "influx-org-id".to_string(),
+                "000000000000abcd".to_string(),
+            )]))
+            .with_timestamp(Some(start))
+            .with_timestamp(Some(start + chrono::Duration::milliseconds(250)))
+            .with_status_code(2);
+        call
+    }
+
+    fn matches(filter: &str) -> bool {
+        let filter: Filter = filter.parse().unwrap();
+        filter.matches(&call())
+    }
+
+    #[test]
+    fn method_matches_full_or_short_name() {
+        assert!(matches("method = TagValues"));
+        assert!(matches(
+            "method = /influxdata.platform.storage.Storage/TagValues"
+        ));
+        assert!(!matches("method = Values"));
+        assert!(matches("method ~ Values"));
+    }
+
+    #[test]
+    fn numbers_and_durations() {
+        assert!(matches("status != 0"));
+        assert!(matches("id >= 42 and id < 43"));
+        assert!(matches("duration > 200ms"));
+        assert!(matches("duration > 0.2s and duration <= 250"));
+        assert!(!matches("duration > 1s"));
+    }
+
+    #[test]
+    fn missing_values_only_match_not_equal() {
+        assert!(!matches("peer = localhost"));
+        assert!(matches("peer != localhost"));
+        assert!(!matches("header.missing ~ x"));
+    }
+
+    #[test]
+    fn ids_match_with_or_without_padding() {
+        assert!(matches("org = 000000000000abcd"));
+        assert!(matches("org = abcd"));
+        assert!(matches("org = ABCD"));
+        assert!(!matches("org = abce"));
+        assert!(!matches("org = not-an-id"));
+
+        let mut call = Call::new(1);
+        call.with_client_headers(HashMap::from([(
+            "influx-org-id".to_string(),
+            "abcd".to_string(),
+        )]));
+        let filter: Filter = "org = 000000000000abcd".parse().unwrap();
+        assert!(filter.matches(&call));
+    }
+
+    #[test]
+    fn precedence() {
+        // not > and > or
+        assert!(matches("status = 0 and id = 1 or org = 000000000000abcd"));
+        assert!(!matches(
+            "status = 0 and (id = 1 or org = 000000000000abcd)"
+        ));
+        assert!(matches(
+            "not status = 0 and header.influx-org-id = '000000000000abcd'"
+        ));
+        assert!(!matches("not (status = 2 or id = 1)"));
+    }
+
+    #[test]
+    fn invalid_filters() {
+        for filter in [
+            "",
+            "method",
+            "method =",
+            "status ~ 1",
+            "method < TagValues",
+            "nope = 1",
+            "duration > 5 parsecs",
+            "duration > 5h",
+            "(status = 0",
+            "status = 0)",
+            "peer = 'unterminated",
+        ] {
+            assert!(filter.parse::<Filter>().is_err(), "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration_ns("200"), Ok(200_000_000));
+        assert_eq!(parse_duration_ns("1.5s"), Ok(1_500_000_000));
+        assert_eq!(parse_duration_ns("3us"), Ok(3_000));
+        assert_eq!(parse_duration_ns("2m"), Ok(120_000_000_000));
+        assert!(parse_duration_ns("ms").is_err());
+    }
+}
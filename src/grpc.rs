@@ -0,0 +1,95 @@
+//! Helpers for talking gRPC with already encoded protobuf messages
+
+use std::collections::HashMap;
+
+use bytes::{Buf, BufMut, Bytes};
+use tonic::{
+    codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder},
+    metadata::{Ascii, MetadataKey, MetadataMap, MetadataValue},
+    Status,
+};
+
+// headers that are managed by the gRPC transport itself, and thus are
+// neither copied onto outgoing requests nor recorded from incoming ones
+const TRANSPORT_HEADERS: &[&str] = &["content-type", "te", "user-agent"];
+
+/// A [`Codec`] that passes message bytes through as is, so that any
+/// gRPC method can be called (or served) with payloads that were
+/// captured in the logs, without knowing their protobuf types.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RawCodec;
+
+impl Codec for RawCodec {
+    type Encode = Bytes;
+    type Decode = Bytes;
+    type Encoder = RawCodec;
+    type Decoder = RawCodec;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        RawCodec
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        RawCodec
+    }
+}
+
+impl Encoder for RawCodec {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        dst.put(item);
+        Ok(())
+    }
+}
+
+impl Decoder for RawCodec {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        Ok(Some(src.copy_to_bytes(src.remaining())))
+    }
+}
+
+fn is_transport_header(name: &str) -> bool {
+    name.starts_with(':') || name.starts_with("grpc-") || TRANSPORT_HEADERS.contains(&name)
+}
+
+/// Converts headers recorded in a [`crate::call::Call`] into gRPC metadata
+pub fn to_metadata(headers: &HashMap<String, String>) -> MetadataMap {
+    let mut metadata = MetadataMap::new();
+
+    for (name, value) in headers {
+        if is_transport_header(name) {
+            continue;
+        }
+
+        let key = MetadataKey::<Ascii>::from_bytes(name.as_bytes());
+        let value = value.parse::<MetadataValue<Ascii>>();
+        match (key, value) {
+            (Ok(key), Ok(value)) => {
+                metadata.insert(key, value);
+            }
+            _ => println!("Not sending header {}", name),
+        }
+    }
+
+    metadata
+}
+
+/// Converts gRPC metadata into headers as recorded in a [`crate::call::Call`]
+pub fn to_hashmap(metadata: MetadataMap) -> HashMap<String, String> {
+    metadata
+        .into_headers()
+        .iter()
+        .filter(|(name, _)| !is_transport_header(name.as_str()))
+        .filter_map(|(name, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|value| (name.to_string(), value.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,32 @@
+//! Reading binary gRPC [logs] that contain requests / responses in the
+//! influxdb storage gRPC format, and reconstructing, decoding and
+//! comparing the calls in them. The `influxrpc_compare` binary is built
+//! on this; other tools can use it to e.g. decode further services by
+//! adding to a [`decoder::MethodRegistry`].
+//!
+//! [logs]: https://github.com/grpc/proposal/blob/master/A16-binary-logging.md
+
+pub mod call;
+pub mod calls;
+pub mod compare;
+pub mod correlate;
+pub mod decoder;
+pub mod descriptors;
+pub mod diff;
+pub mod dump_calls;
+pub mod dump_entries;
+pub mod entries;
+pub mod entry;
+pub mod error;
+pub mod filter;
+pub mod grpc;
+pub mod matcher;
+pub mod methods;
+pub mod path;
+pub mod predicate;
+pub mod replay;
+pub mod serde_protobuf;
+pub mod serve;
+pub mod status;
+pub mod view;
+pub mod wire;
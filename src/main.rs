@@ -1,16 +1,10 @@
-mod call;
-mod calls;
-mod dump_calls;
-mod dump_entries;
-mod entries;
-mod entry;
-mod error;
-mod methods;
-mod path;
-
-use std::{io::stdout, path::PathBuf, str::FromStr};
+use std::{io::stdout, net::SocketAddr, path::PathBuf, str::FromStr};
 
 use clap::Parser;
+use influxrpc_compare::{
+    calls, compare, correlate, decoder, descriptors, diff, dump_calls, dump_entries, error, filter,
+    matcher, replay, serve, view,
+};
 
 /// Command line program for working with binary gRPC [logs] that
 /// contain requests / responses in the influxdb storage gRPC format.
@@ -22,6 +16,18 @@ use clap::Parser;
 /// # Example (dump reconstructed calls from logs in all .txt files found in):
 /// influxrpc_compare dump-calls --path  /path/to/dumps
 ///
+/// # Example (compare calls captured in two directories, or binary call files):
+/// influxrpc_compare compare --left /path/to/dumps1 --right /path/to/calls2.bin
+///
+/// # Example (replay captured calls against a local server, saving the new calls):
+/// influxrpc_compare replay --calls /path/to/dumps --target http://localhost:8082 --out calls.bin
+///
+/// # Example (answer storage gRPC requests with recorded responses):
+/// influxrpc_compare serve --calls /path/to/calls.bin --addr 127.0.0.1:8082
+///
+/// # Example (join client and server side captures of the same calls):
+/// influxrpc_compare correlate --calls /path/to/querier_dumps --calls /path/to/storage_dumps
+///
 /// # Reference
 ///
 /// [logs]: https://github.com/grpc/proposal/blob/master/A16-binary-logging.md
@@ -33,6 +39,14 @@ enum InfluxRpcCompare {
     DumpEntries(DumpEntries),
     /// Dump gRPC calls (reconstructed from log entry files)
     DumpCalls(DumpCalls),
+    /// Compare the responses of equivalent gRPC calls in two captures
+    Compare(Compare),
+    /// Replay captured gRPC calls against a live server
+    Replay(Replay),
+    /// Serve recorded responses from a mock storage gRPC server
+    Serve(Serve),
+    /// Join client and server side captures of the same gRPC calls
+    Correlate(Correlate),
 }
 
 #[derive(Parser, Debug)]
@@ -40,6 +54,10 @@ struct DumpEntries {
     #[clap(long, parse(from_os_str))]
     /// Search path for grpc log files
     path: PathBuf,
+
+    #[clap(long)]
+    /// Skip over corrupted or truncated parts of log files
+    recover: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -59,6 +77,163 @@ struct DumpCalls {
     #[clap(long, default_value = "")]
     /// optional filter on org_id
     org_filter: String,
+
+    #[clap(long, default_value = "")]
+    /// optional filter on bucket_id
+    bucket_filter: String,
+
+    #[clap(long)]
+    /// optional filter expression (e.g. "method = TagValues and duration > 200ms")
+    filter: Option<filter::Filter>,
+
+    #[clap(long)]
+    /// Skip over corrupted or truncated parts of log files
+    recover: bool,
+
+    #[clap(long, parse(from_os_str))]
+    /// optional descriptor set (or .proto file) used to decode other gRPC services
+    descriptors: Option<PathBuf>,
+
+    #[clap(long = "proto-path", parse(from_os_str), multiple_occurrences(true))]
+    /// directories to search for the imports of a .proto file given as --descriptors
+    proto_paths: Vec<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct Compare {
+    #[clap(long, parse(from_os_str))]
+    /// Search path for grpc log files, or binary formatted Calls, of the left capture
+    left: PathBuf,
+
+    #[clap(long, parse(from_os_str))]
+    /// Search path for grpc log files, or binary formatted Calls, of the right capture
+    right: PathBuf,
+
+    #[clap(long)]
+    /// Ignore the order of returned series, groups and tag values
+    ignore_order: bool,
+
+    #[clap(long)]
+    /// Keep comparing ReadGroup groups in order when --ignore-order is set
+    strict_group_order: bool,
+
+    #[clap(long)]
+    /// Only pair calls that have the same uber-trace-id header
+    match_trace_id: bool,
+
+    #[clap(long)]
+    /// Pair requests by their time ranges relative to the start of each call
+    relative_ranges: bool,
+
+    #[clap(long, default_value = "1000")]
+    /// Precision (in milliseconds) of relative time ranges
+    relative_range_precision_ms: i64,
+
+    #[clap(long, default_value = "0")]
+    /// Absolute difference allowed between float values
+    float_abs_epsilon: f64,
+
+    #[clap(long, default_value = "0")]
+    /// Relative difference allowed between float values
+    float_rel_epsilon: f64,
+
+    #[clap(long, default_value = "")]
+    /// optional filter on org_id
+    org_filter: String,
+
+    #[clap(long)]
+    /// optional filter expression (e.g. "method = TagValues and duration > 200ms")
+    filter: Option<filter::Filter>,
+
+    #[clap(long, parse(from_os_str))]
+    /// optional descriptor set (or .proto file) used to decode other gRPC services
+    descriptors: Option<PathBuf>,
+
+    #[clap(long = "proto-path", parse(from_os_str), multiple_occurrences(true))]
+    /// directories to search for the imports of a .proto file given as --descriptors
+    proto_paths: Vec<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct Replay {
+    #[clap(long, parse(from_os_str))]
+    /// Search path for grpc log files, or binary formatted Calls, to replay
+    calls: PathBuf,
+
+    #[clap(long)]
+    /// gRPC server to send the requests to (e.g. http://localhost:8082)
+    target: String,
+
+    #[clap(long = "out", parse(from_os_str))]
+    /// output path for the replayed, binary formatted, Calls
+    output_path: PathBuf,
+
+    #[clap(long, default_value = "")]
+    /// optional filter on org_id
+    org_filter: String,
+
+    #[clap(long)]
+    /// optional filter expression (e.g. "method = TagValues and duration > 200ms")
+    filter: Option<filter::Filter>,
+
+    #[clap(long, parse(from_os_str))]
+    /// optional descriptor set (or .proto file) used to decode other gRPC services
+    descriptors: Option<PathBuf>,
+
+    #[clap(long = "proto-path", parse(from_os_str), multiple_occurrences(true))]
+    /// directories to search for the imports of a .proto file given as --descriptors
+    proto_paths: Vec<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct Serve {
+    #[clap(long, parse(from_os_str))]
+    /// Search path for grpc log files, or binary formatted Calls, to serve
+    calls: PathBuf,
+
+    #[clap(long, default_value = "127.0.0.1:8082")]
+    /// Address to listen on
+    addr: SocketAddr,
+
+    #[clap(long)]
+    /// Match requests by their time ranges relative to when they are received
+    relative_ranges: bool,
+
+    #[clap(long, default_value = "1000")]
+    /// Precision (in milliseconds) of relative time ranges
+    relative_range_precision_ms: i64,
+
+    #[clap(long)]
+    /// optional filter expression (e.g. "method = TagValues and duration > 200ms")
+    filter: Option<filter::Filter>,
+
+    #[clap(long, parse(from_os_str))]
+    /// optional descriptor set (or .proto file) used to decode other gRPC services
+    descriptors: Option<PathBuf>,
+
+    #[clap(long = "proto-path", parse(from_os_str), multiple_occurrences(true))]
+    /// directories to search for the imports of a .proto file given as --descriptors
+    proto_paths: Vec<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct Correlate {
+    #[clap(long, parse(from_os_str), multiple_occurrences(true), required(true))]
+    /// Search paths for grpc log files, or binary formatted Calls, logged
+    /// by clients and servers
+    calls: Vec<PathBuf>,
+
+    #[clap(long)]
+    /// optional filter expression (e.g. "method = TagValues and duration > 200ms")
+    filter: Option<filter::Filter>,
+
+    #[clap(long, parse(from_os_str))]
+    /// optional descriptor set (or .proto file) used to decode other gRPC services
+    descriptors: Option<PathBuf>,
+
+    #[clap(long = "proto-path", parse(from_os_str), multiple_occurrences(true))]
+    /// directories to search for the imports of a .proto file given as --descriptors
+    proto_paths: Vec<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -85,6 +260,7 @@ fn main() {
     match args {
         InfluxRpcCompare::DumpEntries(dump) => {
             dump_entries::DumpEntries::new(dump.path)
+                .with_recovery(dump.recover)
                 .dump(&mut stdout())
                 .expect("Error dumping entries");
         }
@@ -94,7 +270,16 @@ fn main() {
                 return;
             }
 
-            let mut dc = dump_calls::DumpCalls::new(dump.input_path);
+            let decoders = match load_decoders(&dump.descriptors, &dump.proto_paths) {
+                Ok(decoders) => decoders,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let mut dc = dump_calls::DumpCalls::new(dump.input_path)
+                .with_recovery(dump.recover)
+                .with_decoders(decoders);
             let mut calls = match dc.process() {
                 Ok(calls) => calls,
                 Err(e) => {
@@ -118,23 +303,234 @@ fn main() {
                 );
             }
 
+            // Filter by bucket_id
+            if !dump.bucket_filter.is_empty() {
+                let bucket_filter = dump.bucket_filter.as_str();
+                calls.filter_by_bucket_id(bucket_filter);
+                println!(
+                    "Filtered calls not for bucket id {}. {:?} calls remaining",
+                    bucket_filter,
+                    calls.len()
+                );
+            }
+
+            apply_filter(&mut calls, &dump.filter);
+
+            let anomaly_summary = calls.anomaly_summary();
+            let outcome_summary = calls.outcome_summary();
+            let unknown_summary = calls.unknown_summary();
+            let decode_failures: Vec<_> = calls
+                .decode_failures()
+                .map(|(call, method)| format!("call {}: {}", call.id, view::MethodView(method)))
+                .collect();
+
             let res = match dump.format {
                 CallFormat::Pretty => dc.write_calls_pretty(calls, &mut stdout()),
-                CallFormat::Binary => dc.write_calls_binary(
-                    calls,
-                    dump.output_path
-                        .unwrap()
-                        .into_os_string()
-                        .into_string()
-                        .unwrap()
-                        .as_str(),
-                ),
+                CallFormat::Binary => {
+                    dump_calls::DumpCalls::write_calls_binary(calls, &dump.output_path.unwrap())
+                }
             };
 
+            println!("Call outcomes:");
+            for (outcome, count) in outcome_summary {
+                println!("  {:?}: {}", outcome, count);
+            }
+
+            if !anomaly_summary.is_empty() {
+                println!("Anomalies found while reconstructing calls:");
+                for (kind, count) in anomaly_summary {
+                    println!("  {:?}: {}", kind, count);
+                }
+            }
+
+            if !unknown_summary.is_empty() {
+                println!("Messages of methods with no decoder:");
+                for (method_name, count) in unknown_summary {
+                    println!("  {}: {}", method_name, count);
+                }
+            }
+
+            if !decode_failures.is_empty() {
+                println!("{} messages could not be decoded:", decode_failures.len());
+                for failure in decode_failures {
+                    println!("  {}", failure);
+                }
+            }
+
             match res {
                 Ok(_) => println!("Completed successfully"),
                 Err(e) => eprintln!("{}", e),
             }
         }
+        InfluxRpcCompare::Compare(compare) => {
+            let diff_options = diff::DiffOptions {
+                ignore_order: compare.ignore_order,
+                strict_group_order: compare.strict_group_order,
+                float_abs_epsilon: compare.float_abs_epsilon,
+                float_rel_epsilon: compare.float_rel_epsilon,
+            };
+            let relative_range_precision = compare
+                .relative_ranges
+                .then(|| chrono::Duration::milliseconds(compare.relative_range_precision_ms));
+            let matcher = matcher::Matcher::new()
+                .with_match_trace_id(compare.match_trace_id)
+                .with_relative_ranges(relative_range_precision);
+            let decoders = match load_decoders(&compare.descriptors, &compare.proto_paths) {
+                Ok(decoders) => decoders,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let cmp = compare::Compare::new(compare.left, compare.right)
+                .with_decoders(decoders)
+                .with_diff_options(diff_options)
+                .with_matcher(matcher);
+            let (mut left, mut right) = match cmp.load() {
+                Ok(calls) => calls,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+
+            if !compare.org_filter.is_empty() {
+                left.filter_by_org_id(compare.org_filter.as_str());
+                right.filter_by_org_id(compare.org_filter.as_str());
+            }
+            apply_filter(&mut left, &compare.filter);
+            apply_filter(&mut right, &compare.filter);
+
+            let comparison = cmp.compare(&left, &right);
+            if let Err(e) = comparison.write_report(&mut stdout()) {
+                eprintln!("{}", e);
+            }
+        }
+        InfluxRpcCompare::Replay(replay) => {
+            let decoders = match load_decoders(&replay.descriptors, &replay.proto_paths) {
+                Ok(decoders) => decoders,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let mut calls = match compare::load_calls(&replay.calls, &decoders) {
+                Ok(calls) => calls,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+
+            if !replay.org_filter.is_empty() {
+                calls.filter_by_org_id(replay.org_filter.as_str());
+            }
+            apply_filter(&mut calls, &replay.filter);
+
+            let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+            let replayed = match runtime.block_on(
+                replay::Replay::new(replay.target)
+                    .with_decoders(decoders)
+                    .replay(&calls),
+            ) {
+                Ok(replayed) => replayed,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+
+            let res = dump_calls::DumpCalls::write_calls_binary(replayed, &replay.output_path);
+
+            match res {
+                Ok(_) => println!("Completed successfully"),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        InfluxRpcCompare::Serve(serve) => {
+            let decoders = match load_decoders(&serve.descriptors, &serve.proto_paths) {
+                Ok(decoders) => decoders,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let mut calls = match compare::load_calls(&serve.calls, &decoders) {
+                Ok(calls) => calls,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            apply_filter(&mut calls, &serve.filter);
+
+            let relative_range_precision = serve
+                .relative_ranges
+                .then(|| chrono::Duration::milliseconds(serve.relative_range_precision_ms));
+            let matcher = matcher::Matcher::new().with_relative_ranges(relative_range_precision);
+
+            let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+            let res = runtime.block_on(
+                serve::Serve::new(serve.addr)
+                    .with_matcher(matcher)
+                    .with_decoders(decoders)
+                    .serve(calls),
+            );
+
+            if let Err(e) = res {
+                eprintln!("{}", e);
+            }
+        }
+        InfluxRpcCompare::Correlate(correlate) => {
+            let decoders = match load_decoders(&correlate.descriptors, &correlate.proto_paths) {
+                Ok(decoders) => decoders,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let mut calls = calls::Calls::default();
+            for path in &correlate.calls {
+                match compare::load_calls(path, &decoders) {
+                    Ok(loaded) => calls.extend_from_other(loaded),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                }
+            }
+
+            apply_filter(&mut calls, &correlate.filter);
+
+            let correlation = correlate::correlate(&calls);
+            if let Err(e) = correlation.write_report(&mut stdout()) {
+                eprintln!("{}", e);
+            }
+        }
     };
 }
+
+/// Drops the calls that do not match `filter`, if there is one
+fn apply_filter(calls: &mut calls::Calls, filter: &Option<filter::Filter>) {
+    if let Some(filter) = filter {
+        calls.filter_by_expression(filter);
+        println!(
+            "Filtered calls not matching {}. {:?} calls remaining",
+            filter,
+            calls.len()
+        );
+    }
+}
+
+/// Decoders for the storage service, and for the services in
+/// `descriptors`, if given
+fn load_decoders(
+    descriptors: &Option<PathBuf>,
+    proto_paths: &[PathBuf],
+) -> error::Result<decoder::MethodRegistry> {
+    let decoders = decoder::MethodRegistry::default();
+    match descriptors {
+        Some(path) => Ok(descriptors::Descriptors::load(path, proto_paths)?.register(decoders)),
+        None => Ok(decoders),
+    }
+}
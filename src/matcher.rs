@@ -0,0 +1,518 @@
+//! Pairs up equivalent calls from two different captures
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::Duration;
+use generated_types::{
+    google::protobuf::Any,
+    influxdata::platform::storage::{Predicate, TimestampRange},
+};
+
+use crate::{
+    call::Call,
+    calls::Calls,
+    methods::{decode_read_source, Method, READ_SOURCE_TYPE_URL},
+    predicate::format_predicate,
+};
+
+// gRPC header key used by the tracing system to identify a request
+pub const TRACE_ID_HEADER_NAME: &str = "uber-trace-id";
+
+/// Identifies calls that are considered equivalent across captures
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CallKey {
+    method_name: Option<String>,
+    org_id: Option<String>,
+    trace_id: Option<String>,
+    /// Normalized request (without its predicate), formatted with
+    /// `Debug` as the generated protobuf types can not be hashed
+    request: String,
+    /// The request's predicate as an expression, so that equivalent
+    /// predicates match however their expressions were nested
+    predicate: Option<String>,
+}
+
+/// Calls from two captures paired up by [`Matcher`]
+#[derive(Debug, Default)]
+pub struct Matches<'a> {
+    /// (left, right) calls with equivalent requests
+    pub pairs: Vec<(&'a Call, &'a Call)>,
+
+    /// Calls in the left capture with no equivalent in the right capture
+    pub unmatched_left: Vec<&'a Call>,
+
+    /// Calls in the right capture with no equivalent in the left capture
+    pub unmatched_right: Vec<&'a Call>,
+}
+
+/// Pairs calls from two captures whose `Call::id`s are unrelated, by
+/// keying each call on its method name, normalized request and org
+/// id (and optionally its trace id header).
+#[derive(Debug, Default, Clone)]
+pub struct Matcher {
+    match_trace_id: bool,
+    relative_range_precision: Option<Duration>,
+}
+
+impl Matcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also require the `uber-trace-id` client header to match
+    pub fn with_match_trace_id(mut self, match_trace_id: bool) -> Self {
+        self.match_trace_id = match_trace_id;
+        self
+    }
+
+    /// Rewrite request time ranges relative to the call's start time,
+    /// rounded to `precision`, so that queries for e.g. "the last hour"
+    /// issued at different wall clock times are considered equivalent
+    pub fn with_relative_ranges(mut self, precision: Option<Duration>) -> Self {
+        self.relative_range_precision = precision;
+        self
+    }
+
+    pub fn key(&self, call: &Call) -> CallKey {
+        let trace_id = if self.match_trace_id {
+            call.client_headers.get(TRACE_ID_HEADER_NAME).cloned()
+        } else {
+            None
+        };
+
+        let (request, predicate) = self.normalize_request(call);
+        CallKey {
+            method_name: call.method_name.clone(),
+            org_id: call.org_id(),
+            trace_id,
+            request: format!("{:?}", request),
+            predicate: predicate.as_ref().map(format_predicate),
+        }
+    }
+
+    /// Pairs each call in `left` with an equivalent call in `right`.
+    /// Equivalent calls that are issued multiple times are paired in
+    /// the order they occur in each capture.
+    pub fn match_calls<'a>(&self, left: &'a Calls, right: &'a Calls) -> Matches<'a> {
+        let mut right_by_key: HashMap<CallKey, VecDeque<(usize, &'a Call)>> = HashMap::new();
+        for (i, call) in right.iter().enumerate() {
+            right_by_key
+                .entry(self.key(call))
+                .or_default()
+                .push_back((i, call));
+        }
+
+        let mut matches = Matches::default();
+        for left_call in left.iter() {
+            let right_call = right_by_key
+                .get_mut(&self.key(left_call))
+                .and_then(|candidates| candidates.pop_front());
+
+            match right_call {
+                Some((_, right_call)) => matches.pairs.push((left_call, right_call)),
+                None => matches.unmatched_left.push(left_call),
+            }
+        }
+
+        // report leftovers in capture order
+        let mut unmatched_right: Vec<_> = right_by_key.into_values().flatten().collect();
+        unmatched_right.sort_unstable_by_key(|(i, _)| *i);
+        matches.unmatched_right = unmatched_right.into_iter().map(|(_, call)| call).collect();
+
+        matches
+    }
+
+    /// Returns a copy of the call's request with the parts that can
+    /// differ between equivalent requests normalized, and its predicate
+    /// taken out
+    fn normalize_request(&self, call: &Call) -> (Option<Method>, Option<Predicate>) {
+        let mut request = match call.request.clone() {
+            Some(request) => request,
+            None => return (None, None),
+        };
+        let mut taken_predicate = None;
+
+        let parts = match &mut request {
+            Method::TagKeysRequest(r) => {
+                Some((&mut r.tags_source, Some(&mut r.range), &mut r.predicate))
+            }
+            Method::TagValuesRequest(r) => {
+                Some((&mut r.tags_source, Some(&mut r.range), &mut r.predicate))
+            }
+            Method::ReadFilterRequest(r) => {
+                Some((&mut r.read_source, Some(&mut r.range), &mut r.predicate))
+            }
+            Method::ReadGroupRequest(r) => {
+                Some((&mut r.read_source, Some(&mut r.range), &mut r.predicate))
+            }
+            Method::ReadWindowAggregateRequest(r) => {
+                Some((&mut r.read_source, Some(&mut r.range), &mut r.predicate))
+            }
+            Method::MeasurementNamesRequest(r) => {
+                Some((&mut r.source, Some(&mut r.range), &mut r.predicate))
+            }
+            Method::MeasurementTagKeysRequest(r) => {
+                Some((&mut r.source, Some(&mut r.range), &mut r.predicate))
+            }
+            Method::MeasurementTagValuesRequest(r) => {
+                Some((&mut r.source, Some(&mut r.range), &mut r.predicate))
+            }
+            Method::MeasurementFieldsRequest(r) => {
+                Some((&mut r.source, Some(&mut r.range), &mut r.predicate))
+            }
+            Method::ReadSeriesCardinalityRequest(r) => Some((
+                &mut r.read_series_cardinality_source,
+                Some(&mut r.range),
+                &mut r.predicate,
+            )),
+            // has no range
+            Method::TagValuesGroupedByMeasurementAndTagKeyRequest(r) => {
+                Some((&mut r.source, None, &mut r.condition))
+            }
+            _ => None,
+        };
+
+        if let Some((source, range, predicate)) = parts {
+            normalize_source(source);
+            normalize_predicate(predicate);
+            taken_predicate = predicate.take();
+
+            if let Some(range) = range {
+                normalize_range(range);
+
+                let origin = call
+                    .start_time
+                    .map(|start_time| start_time.timestamp_nanos());
+                let precision = self
+                    .relative_range_precision
+                    .and_then(|precision| precision.num_nanoseconds());
+                if let (Some(origin), Some(precision)) = (origin, precision) {
+                    relative_range(range, origin, precision);
+                }
+            }
+        }
+
+        (Some(request), taken_predicate)
+    }
+}
+
+/// Re-encodes the `ReadSource` with a canonical type URL and without
+/// the partition id, which depends on the storage node that was asked
+fn normalize_source(source: &mut Option<Any>) {
+    let read_source = source.as_ref().and_then(decode_read_source);
+
+    if let Some(mut read_source) = read_source {
+        use prost::Message;
+
+        read_source.partition_id = 0;
+        *source = Some(Any {
+            type_url: READ_SOURCE_TYPE_URL.to_string(),
+            value: read_source.encode_to_vec().into(),
+        });
+    }
+}
+
+/// A missing range means "all time"
+fn normalize_range(range: &mut Option<TimestampRange>) {
+    if range.is_none() {
+        *range = Some(TimestampRange {
+            start: i64::MIN,
+            end: i64::MAX,
+        });
+    }
+}
+
+/// A predicate without a root node does not filter anything
+fn normalize_predicate(predicate: &mut Option<Predicate>) {
+    if predicate
+        .as_ref()
+        .map(|p| p.root.is_none())
+        .unwrap_or(false)
+    {
+        *predicate = None;
+    }
+}
+
+/// Rewrites the (nanosecond) bounds of `range` as offsets from
+/// `origin`, rounded to the nearest multiple of `precision`. The
+/// open ended "all time" bounds are left as is.
+fn relative_range(range: &mut Option<TimestampRange>, origin: i64, precision: i64) {
+    let relative = |ts: i64| {
+        if ts == i64::MIN || ts == i64::MAX || precision <= 0 {
+            return ts;
+        }
+        let offset = ts.saturating_sub(origin);
+        offset
+            .saturating_add(precision / 2)
+            .div_euclid(precision)
+            .saturating_mul(precision)
+    };
+
+    if let Some(range) = range {
+        range.start = relative(range.start);
+        range.end = relative(range.end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+    use generated_types::influxdata::platform::storage::{
+        node::{Comparison, Logical, Type, Value},
+        Node, ReadFilterRequest, ReadSource,
+    };
+    use prost::Message;
+
+    use super::*;
+
+    fn source(bucket_id: u64, partition_id: u64) -> Option<Any> {
+        let read_source = ReadSource {
+            org_id: 0xabcd,
+            bucket_id,
+            partition_id,
+        };
+        Some(Any {
+            type_url: READ_SOURCE_TYPE_URL.to_string(),
+            value: read_source.encode_to_vec().into(),
+        })
+    }
+
+    fn request(
+        source: Option<Any>,
+        range: Option<TimestampRange>,
+        predicate: Option<Predicate>,
+    ) -> Method {
+        Method::ReadFilterRequest(ReadFilterRequest {
+            read_source: source,
+            range,
+            predicate,
+        })
+    }
+
+    fn range(start: i64, end: i64) -> Option<TimestampRange> {
+        Some(TimestampRange { start, end })
+    }
+
+    fn call(id: u64, request: Method) -> Call {
+        let mut call = Call::new(id);
+        call.with_method_name("/influxdata.platform.storage.Storage/ReadFilter".to_string())
+            .request = Some(request);
+        call
+    }
+
+    fn call_at(id: u64, start_time: DateTime<Utc>, request: Method) -> Call {
+        let mut call = call(id, request);
+        call.with_timestamp(Some(start_time));
+        call
+    }
+
+    fn ids(calls: &[&Call]) -> Vec<u64> {
+        calls.iter().map(|call| call.id).collect()
+    }
+
+    fn pair_ids(matches: &Matches<'_>) -> Vec<(u64, u64)> {
+        matches
+            .pairs
+            .iter()
+            .map(|(left, right)| (left.id, right.id))
+            .collect()
+    }
+
+    fn tag_equals(key: &str, value: &str) -> Node {
+        Node {
+            node_type: Type::ComparisonExpression as i32,
+            children: vec![
+                Node {
+                    node_type: Type::TagRef as i32,
+                    children: vec![],
+                    value: Some(Value::TagRefValue(key.as_bytes().to_vec())),
+                },
+                Node {
+                    node_type: Type::Literal as i32,
+                    children: vec![],
+                    value: Some(Value::StringValue(value.to_string())),
+                },
+            ],
+            value: Some(Value::Comparison(Comparison::Equal as i32)),
+        }
+    }
+
+    fn and(children: Vec<Node>) -> Node {
+        Node {
+            node_type: Type::LogicalExpression as i32,
+            children,
+            value: Some(Value::Logical(Logical::And as i32)),
+        }
+    }
+
+    #[test]
+    fn duplicate_requests_are_paired_in_order() {
+        let left = Calls::from(vec![
+            call(1, request(source(1, 0), range(0, 10), None)),
+            call(2, request(source(1, 0), range(0, 10), None)),
+        ]);
+        let right = Calls::from(vec![
+            call(10, request(source(1, 0), range(0, 10), None)),
+            call(11, request(source(1, 0), range(0, 10), None)),
+            call(12, request(source(1, 0), range(0, 10), None)),
+        ]);
+
+        let matches = Matcher::new().match_calls(&left, &right);
+        assert_eq!(pair_ids(&matches), vec![(1, 10), (2, 11)]);
+        assert!(matches.unmatched_left.is_empty());
+        assert_eq!(ids(&matches.unmatched_right), vec![12]);
+    }
+
+    #[test]
+    fn partition_id_is_ignored() {
+        let left = Calls::from(vec![
+            call(1, request(source(1, 1), range(0, 10), None)),
+            call(2, request(source(1, 1), range(0, 10), None)),
+        ]);
+        let right = Calls::from(vec![
+            call(10, request(source(1, 2), range(0, 10), None)),
+            call(11, request(source(2, 1), range(0, 10), None)),
+        ]);
+
+        let matches = Matcher::new().match_calls(&left, &right);
+        assert_eq!(pair_ids(&matches), vec![(1, 10)]);
+        assert_eq!(ids(&matches.unmatched_left), vec![2]);
+        assert_eq!(ids(&matches.unmatched_right), vec![11]);
+    }
+
+    #[test]
+    fn equivalent_requests_match() {
+        let a = || tag_equals("host", "a");
+        let b = || tag_equals("region", "b");
+        let c = || tag_equals("az", "c");
+        let nested_right = Predicate {
+            root: Some(and(vec![a(), and(vec![b(), c()])])),
+        };
+        let nested_left = Predicate {
+            root: Some(and(vec![and(vec![a(), b()]), c()])),
+        };
+
+        let left = Calls::from(vec![
+            call(1, request(source(1, 0), range(0, 10), Some(nested_left))),
+            call(2, request(source(1, 0), None, None)),
+            call(3, request(source(1, 0), range(0, 10), None)),
+        ]);
+        let right = Calls::from(vec![
+            call(10, request(source(1, 0), range(0, 10), Some(nested_right))),
+            // a missing range is all time
+            call(11, request(source(1, 0), range(i64::MIN, i64::MAX), None)),
+            // a predicate without a root does not filter anything
+            call(
+                12,
+                request(source(1, 0), range(0, 10), Some(Predicate { root: None })),
+            ),
+        ]);
+
+        let matches = Matcher::new().match_calls(&left, &right);
+        assert_eq!(pair_ids(&matches), vec![(1, 10), (2, 11), (3, 12)]);
+    }
+
+    #[test]
+    fn unmatched_calls_are_reported_on_each_side() {
+        let left = Calls::from(vec![
+            call(1, request(source(1, 0), range(0, 10), None)),
+            call(2, request(source(1, 0), range(0, 20), None)),
+            call(3, request(source(3, 0), range(0, 10), None)),
+        ]);
+        let right = Calls::from(vec![
+            call(10, request(source(4, 0), range(0, 10), None)),
+            call(11, request(source(1, 0), range(0, 10), None)),
+            call(12, request(source(1, 0), range(0, 30), None)),
+        ]);
+
+        let matches = Matcher::new().match_calls(&left, &right);
+        assert_eq!(pair_ids(&matches), vec![(1, 11)]);
+        assert_eq!(ids(&matches.unmatched_left), vec![2, 3]);
+        assert_eq!(ids(&matches.unmatched_right), vec![10, 12]);
+    }
+
+    #[test]
+    fn trace_ids_are_only_matched_if_asked_to() {
+        let mut left = call(1, request(source(1, 0), range(0, 10), None));
+        left.client_headers
+            .insert(TRACE_ID_HEADER_NAME.to_string(), "a".to_string());
+        let mut right = call(10, request(source(1, 0), range(0, 10), None));
+        right
+            .client_headers
+            .insert(TRACE_ID_HEADER_NAME.to_string(), "b".to_string());
+        let left = Calls::from(vec![left]);
+        let right = Calls::from(vec![right]);
+
+        let matches = Matcher::new().match_calls(&left, &right);
+        assert_eq!(pair_ids(&matches), vec![(1, 10)]);
+
+        let matches = Matcher::new()
+            .with_match_trace_id(true)
+            .match_calls(&left, &right);
+        assert!(matches.pairs.is_empty());
+    }
+
+    #[test]
+    fn relative_ranges_round_to_precision() {
+        let relative = |start, end| {
+            let mut range = range(start, end);
+            relative_range(&mut range, 1_000, 100);
+            range.map(|range| (range.start, range.end)).unwrap()
+        };
+
+        assert_eq!(relative(1_000, 1_049), (0, 0));
+        assert_eq!(relative(1_000, 1_050), (0, 100));
+        assert_eq!(relative(951, 1_149), (0, 100));
+        assert_eq!(relative(950, 1_150), (0, 200));
+        assert_eq!(relative(900, 2_000), (-100, 1_000));
+        assert_eq!(relative(849, 1_000), (-200, 0));
+        // "all time" stays open ended
+        assert_eq!(relative(i64::MIN, i64::MAX), (i64::MIN, i64::MAX));
+
+        let mut all_time = None;
+        relative_range(&mut all_time, 1_000, 100);
+        assert!(all_time.is_none());
+    }
+
+    #[test]
+    fn relative_ranges_match_within_precision() {
+        let hour = 3_600_000_000_000;
+        let start = Utc.timestamp(1_644_522_055, 0);
+        let later = start + Duration::minutes(10);
+        let last_hour = |now: DateTime<Utc>| {
+            let now = now.timestamp_nanos();
+            request(source(1, 0), range(now - hour, now), None)
+        };
+        let matcher = Matcher::new().with_relative_ranges(Some(Duration::seconds(1)));
+
+        // "the last hour" asked 10 minutes apart, with the call starting
+        // a little after the range was computed
+        let left = Calls::from(vec![call_at(1, start, last_hour(start))]);
+        let right = Calls::from(vec![
+            call_at(10, later + Duration::milliseconds(600), last_hour(later)),
+            call_at(11, later + Duration::milliseconds(400), last_hour(later)),
+        ]);
+        let matches = matcher.match_calls(&left, &right);
+        assert_eq!(pair_ids(&matches), vec![(1, 11)]);
+
+        // without relative ranges, they are different requests
+        let matches = Matcher::new().match_calls(&left, &right);
+        assert!(matches.pairs.is_empty());
+    }
+
+    #[test]
+    fn same_absolute_range_at_different_times_does_not_match() {
+        let start = Utc.timestamp(1_644_522_055, 0);
+        let absolute = || request(source(1, 0), range(0, start.timestamp_nanos()), None);
+        let matcher = Matcher::new().with_relative_ranges(Some(Duration::seconds(1)));
+
+        let left = Calls::from(vec![call_at(1, start, absolute())]);
+        let right = Calls::from(vec![call_at(10, start + Duration::minutes(10), absolute())]);
+        let matches = matcher.match_calls(&left, &right);
+        assert!(matches.pairs.is_empty());
+
+        // but does without relative ranges
+        let matches = Matcher::new().match_calls(&left, &right);
+        assert_eq!(pair_ids(&matches), vec![(1, 10)]);
+    }
+}
@@ -1,52 +1,138 @@
 use bytes::Bytes;
-use generated_types::influxdata::platform::storage::{
-    CapabilitiesResponse, OffsetsResponse, ReadFilterRequest, ReadGroupRequest, ReadResponse,
-    ReadWindowAggregateRequest, StringValuesResponse, TagKeysRequest, TagValuesRequest,
+use generated_types::{
+    google::protobuf::Any,
+    influxdata::platform::storage::{
+        CapabilitiesResponse, Int64ValuesResponse, MeasurementFieldsRequest,
+        MeasurementFieldsResponse, MeasurementNamesRequest, MeasurementTagKeysRequest,
+        MeasurementTagValuesRequest, OffsetsResponse, Predicate, ReadFilterRequest,
+        ReadGroupRequest, ReadResponse, ReadSeriesCardinalityRequest, ReadSource,
+        ReadWindowAggregateRequest, StringValuesResponse, TagKeysRequest,
+        TagValuesGroupedByMeasurementAndTagKeyRequest, TagValuesRequest, TagValuesResponse,
+    },
 };
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+use crate::{decoder::MethodDecoder, descriptors::DynamicMessage};
+
+/// Name of the IOx storage gRPC service
+pub const STORAGE_SERVICE_NAME: &str = "influxdata.platform.storage.Storage";
+
+/// Type URL of the `Any` sources sent with every storage request
+pub const READ_SOURCE_TYPE_URL: &str =
+    "type.googleapis.com/com.github.influxdata.idpe.storage.read.ReadSource";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum MethodType {
     Request,
     Response,
 }
 
 /// All the GRPC methods this code knows how to decode to native form
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub enum Method {
     /// `/influxdata.platform.storage.Storage/Offsets`
     /// No special decoding (yet)
     StorageOffsetsRequest(Bytes),
-    StorageOffsetsResponse(OffsetsResponse),
+    StorageOffsetsResponse(#[serde(with = "crate::serde_protobuf")] OffsetsResponse),
 
     /// `/influxdata.platform.storage.Storage/TagKeys`
-    TagKeysRequest(TagKeysRequest),
+    TagKeysRequest(#[serde(with = "crate::serde_protobuf")] TagKeysRequest),
 
     /// /influxdata.platform.storage.Storage/TagValues
-    TagValuesRequest(TagValuesRequest),
+    TagValuesRequest(#[serde(with = "crate::serde_protobuf")] TagValuesRequest),
 
     /// Request: `/influxdata.platform.storage.Storage/Capabilities`
     CapabilitiesRequest(),
     /// Response: `/influxdata.platform.storage.Storage/Capabilities`
-    CapabilitiesResponse(CapabilitiesResponse),
+    CapabilitiesResponse(#[serde(with = "crate::serde_protobuf")] CapabilitiesResponse),
 
     /// Request `/influxdata.platform.storage.Storage/ReadFilter`
-    ReadFilterRequest(ReadFilterRequest),
+    ReadFilterRequest(#[serde(with = "crate::serde_protobuf")] ReadFilterRequest),
 
     /// Request `/influxdata.platform.storage.Storage/ReadGroup`
-    ReadGroupRequest(ReadGroupRequest),
+    ReadGroupRequest(#[serde(with = "crate::serde_protobuf")] ReadGroupRequest),
 
     /// Request `/influxdata.platform.storage.Storage/ReadWindowAggregate`
-    ReadWindowAggregateRequest(ReadWindowAggregateRequest),
+    ReadWindowAggregateRequest(#[serde(with = "crate::serde_protobuf")] ReadWindowAggregateRequest),
 
     /// Response for:
-    /// `/influxdata.platform.storage.Storage/ReadWindowAggregate`
-    ReadResponse(ReadResponse),
+    /// * `/influxdata.platform.storage.Storage/ReadFilter`
+    /// * `/influxdata.platform.storage.Storage/ReadGroup`
+    /// * `/influxdata.platform.storage.Storage/ReadWindowAggregate`
+    ReadResponse(#[serde(with = "crate::serde_protobuf")] ReadResponse),
+
+    /// Request `/influxdata.platform.storage.Storage/MeasurementNames`
+    MeasurementNamesRequest(#[serde(with = "crate::serde_protobuf")] MeasurementNamesRequest),
+
+    /// Request `/influxdata.platform.storage.Storage/MeasurementTagKeys`
+    MeasurementTagKeysRequest(#[serde(with = "crate::serde_protobuf")] MeasurementTagKeysRequest),
+
+    /// Request `/influxdata.platform.storage.Storage/MeasurementTagValues`
+    MeasurementTagValuesRequest(
+        #[serde(with = "crate::serde_protobuf")] MeasurementTagValuesRequest,
+    ),
+
+    /// Request `/influxdata.platform.storage.Storage/MeasurementFields`
+    MeasurementFieldsRequest(#[serde(with = "crate::serde_protobuf")] MeasurementFieldsRequest),
+    /// Response `/influxdata.platform.storage.Storage/MeasurementFields`
+    MeasurementFieldsResponse(#[serde(with = "crate::serde_protobuf")] MeasurementFieldsResponse),
+
+    /// Request `/influxdata.platform.storage.Storage/ReadSeriesCardinality`
+    ReadSeriesCardinalityRequest(
+        #[serde(with = "crate::serde_protobuf")] ReadSeriesCardinalityRequest,
+    ),
+    /// Response `/influxdata.platform.storage.Storage/ReadSeriesCardinality`
+    Int64ValuesResponse(#[serde(with = "crate::serde_protobuf")] Int64ValuesResponse),
+
+    /// Request `/influxdata.platform.storage.Storage/TagValuesGroupedByMeasurementAndTagKey`
+    TagValuesGroupedByMeasurementAndTagKeyRequest(
+        #[serde(with = "crate::serde_protobuf")] TagValuesGroupedByMeasurementAndTagKeyRequest,
+    ),
+    /// Response `/influxdata.platform.storage.Storage/TagValuesGroupedByMeasurementAndTagKey`
+    TagValuesResponse(#[serde(with = "crate::serde_protobuf")] TagValuesResponse),
 
     /// Response for:
     /// * `/influxdata.platform.storage.Storage/TagKeys`
     /// * `/influxdata.platform.storage.Storage/TagValues`
-    StringValuesResponse(StringValuesResponse),
+    /// * `/influxdata.platform.storage.Storage/MeasurementNames`
+    /// * `/influxdata.platform.storage.Storage/MeasurementTagKeys`
+    /// * `/influxdata.platform.storage.Storage/MeasurementTagValues`
+    StringValuesResponse(#[serde(with = "crate::serde_protobuf")] StringValuesResponse),
+
+    /// A message that was truncated by the logger, as it was larger
+    /// than the configured maximum
+    Truncated {
+        /// The length of the whole message
+        length: u32,
+        /// The bytes that were captured
+        bytes: Bytes,
+        /// The message decoded from the leading fields that were
+        /// captured in full, if any
+        partial: Option<Box<Method>>,
+    },
+
+    /// A message of a known gRPC method that could not be decoded,
+    /// e.g. because it is malformed or from a different version of
+    /// the protocol
+    DecodeError {
+        /// The name of the gRPC method
+        method_name: String,
+        method_type: MethodType,
+        /// The raw data that went in
+        bytes: Bytes,
+        /// Why decoding failed
+        error: String,
+    },
+
+    /// A message decoded with descriptors loaded at runtime (see
+    /// `--descriptors`)
+    Dynamic {
+        /// The name of the gRPC method
+        method_name: String,
+        /// The raw data that went in
+        bytes: Bytes,
+        message: DynamicMessage,
+    },
 
     /// a gRPC Method we don't (yet) know how to decode
     Unknown {
@@ -58,77 +144,420 @@ pub enum Method {
 }
 
 impl Method {
-    pub fn new(method_name: impl Into<String>, data: Vec<u8>, method_type: MethodType) -> Self {
-        use prost::Message;
+    /// The decoded `ReadSource` of storage requests (which all carry one)
+    pub fn read_source(&self) -> Option<ReadSource> {
+        let source = match self {
+            Self::TagKeysRequest(r) => r.tags_source.as_ref(),
+            Self::TagValuesRequest(r) => r.tags_source.as_ref(),
+            Self::ReadFilterRequest(r) => r.read_source.as_ref(),
+            Self::ReadGroupRequest(r) => r.read_source.as_ref(),
+            Self::ReadWindowAggregateRequest(r) => r.read_source.as_ref(),
+            Self::MeasurementNamesRequest(r) => r.source.as_ref(),
+            Self::MeasurementTagKeysRequest(r) => r.source.as_ref(),
+            Self::MeasurementTagValuesRequest(r) => r.source.as_ref(),
+            Self::MeasurementFieldsRequest(r) => r.source.as_ref(),
+            Self::ReadSeriesCardinalityRequest(r) => r.read_series_cardinality_source.as_ref(),
+            Self::TagValuesGroupedByMeasurementAndTagKeyRequest(r) => r.source.as_ref(),
+            Self::Truncated { partial, .. } => return partial.as_ref()?.read_source(),
+            _ => None,
+        };
+        source.and_then(decode_read_source)
+    }
+
+    /// The predicate of storage requests that have one
+    pub fn predicate(&self) -> Option<&Predicate> {
+        match self {
+            Self::TagKeysRequest(r) => r.predicate.as_ref(),
+            Self::TagValuesRequest(r) => r.predicate.as_ref(),
+            Self::ReadFilterRequest(r) => r.predicate.as_ref(),
+            Self::ReadGroupRequest(r) => r.predicate.as_ref(),
+            Self::ReadWindowAggregateRequest(r) => r.predicate.as_ref(),
+            Self::MeasurementNamesRequest(r) => r.predicate.as_ref(),
+            Self::MeasurementTagKeysRequest(r) => r.predicate.as_ref(),
+            Self::MeasurementTagValuesRequest(r) => r.predicate.as_ref(),
+            Self::MeasurementFieldsRequest(r) => r.predicate.as_ref(),
+            Self::ReadSeriesCardinalityRequest(r) => r.predicate.as_ref(),
+            Self::TagValuesGroupedByMeasurementAndTagKeyRequest(r) => r.condition.as_ref(),
+            Self::Truncated { partial, .. } => partial.as_ref()?.predicate(),
+            _ => None,
+        }
+    }
+
+    pub fn is_decode_error(&self) -> bool {
+        matches!(self, Self::DecodeError { .. })
+    }
+}
+
+/// Decodes the methods of the `influxdata.platform.storage.Storage` service
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StorageDecoder;
+
+impl MethodDecoder for StorageDecoder {
+    fn decode(
+        &self,
+        method_name: &str,
+        bytes: &Bytes,
+        method_type: MethodType,
+    ) -> Result<Method, String> {
         use MethodType::*;
-        let method_name = method_name.into();
-        let bytes: Bytes = data.into();
 
-        match (method_name.as_str(), method_type) {
+        match (method_name, method_type) {
             ("/influxdata.platform.storage.Storage/Offsets", Request) => {
-                Self::StorageOffsetsRequest(bytes)
+                Ok(Method::StorageOffsetsRequest(bytes.clone()))
             }
             ("/influxdata.platform.storage.Storage/Offsets", Response) => {
-                let msg = OffsetsResponse::decode(bytes).expect("Error decoding OffsetsResponse");
-                Self::StorageOffsetsResponse(msg)
+                decode(bytes, Method::StorageOffsetsResponse)
             }
             ("/influxdata.platform.storage.Storage/Capabilities", Request) => {
-                assert!(
-                    bytes.is_empty(),
-                    "Unexpected request payload for storage/capabilities"
-                );
-                Self::CapabilitiesRequest()
+                if bytes.is_empty() {
+                    Ok(Method::CapabilitiesRequest())
+                } else {
+                    Err("Unexpected request payload for storage/capabilities".to_string())
+                }
             }
             ("/influxdata.platform.storage.Storage/Capabilities", Response) => {
-                let msg = CapabilitiesResponse::decode(bytes)
-                    .expect("Error decoding CapabilitiesResponse");
-                Self::CapabilitiesResponse(msg)
+                decode(bytes, Method::CapabilitiesResponse)
             }
             ("/influxdata.platform.storage.Storage/TagKeys", Request) => {
-                let msg = TagKeysRequest::decode(bytes).expect("Error decoding TagKeysRequest");
-                Self::TagKeysRequest(msg)
+                decode(bytes, Method::TagKeysRequest)
             }
             ("/influxdata.platform.storage.Storage/TagValues", Request) => {
-                let msg = TagValuesRequest::decode(bytes).expect("Error decoding TagValuesRequest");
-                Self::TagValuesRequest(msg)
+                decode(bytes, Method::TagValuesRequest)
+            }
+            ("/influxdata.platform.storage.Storage/MeasurementNames", Request) => {
+                decode(bytes, Method::MeasurementNamesRequest)
+            }
+            ("/influxdata.platform.storage.Storage/MeasurementTagKeys", Request) => {
+                decode(bytes, Method::MeasurementTagKeysRequest)
+            }
+            ("/influxdata.platform.storage.Storage/MeasurementTagValues", Request) => {
+                decode(bytes, Method::MeasurementTagValuesRequest)
             }
             ("/influxdata.platform.storage.Storage/TagKeys", Response)
-            | ("/influxdata.platform.storage.Storage/TagValues", Response) => {
-                let msg = StringValuesResponse::decode(bytes)
-                    .expect("Error decoding StringValuesResponse");
-                Self::StringValuesResponse(msg)
+            | ("/influxdata.platform.storage.Storage/TagValues", Response)
+            | ("/influxdata.platform.storage.Storage/MeasurementNames", Response)
+            | ("/influxdata.platform.storage.Storage/MeasurementTagKeys", Response)
+            | ("/influxdata.platform.storage.Storage/MeasurementTagValues", Response) => {
+                decode(bytes, Method::StringValuesResponse)
+            }
+            ("/influxdata.platform.storage.Storage/MeasurementFields", Request) => {
+                decode(bytes, Method::MeasurementFieldsRequest)
+            }
+            ("/influxdata.platform.storage.Storage/MeasurementFields", Response) => {
+                decode(bytes, Method::MeasurementFieldsResponse)
             }
+            ("/influxdata.platform.storage.Storage/ReadSeriesCardinality", Request) => {
+                decode(bytes, Method::ReadSeriesCardinalityRequest)
+            }
+            ("/influxdata.platform.storage.Storage/ReadSeriesCardinality", Response) => {
+                decode(bytes, Method::Int64ValuesResponse)
+            }
+            (
+                "/influxdata.platform.storage.Storage/TagValuesGroupedByMeasurementAndTagKey",
+                Request,
+            ) => decode(bytes, Method::TagValuesGroupedByMeasurementAndTagKeyRequest),
+            (
+                "/influxdata.platform.storage.Storage/TagValuesGroupedByMeasurementAndTagKey",
+                Response,
+            ) => decode(bytes, Method::TagValuesResponse),
             ("/influxdata.platform.storage.Storage/ReadWindowAggregate", Request) => {
-                let msg = ReadWindowAggregateRequest::decode(bytes)
-                    .expect("Error decoding ReadWindowAggregateRequest");
-                Self::ReadWindowAggregateRequest(msg)
+                decode(bytes, Method::ReadWindowAggregateRequest)
             }
             ("/influxdata.platform.storage.Storage/ReadFilter", Request) => {
-                let msg = ReadFilterRequest::decode(bytes)
-                    .expect("Error decoding ReadWindowAggregateRequest");
-                Self::ReadFilterRequest(msg)
+                decode(bytes, Method::ReadFilterRequest)
             }
             ("/influxdata.platform.storage.Storage/ReadGroup", Request) => {
-                let msg = ReadGroupRequest::decode(bytes)
-                    .expect("Error decoding ReadWindowAggregateRequest");
-                Self::ReadGroupRequest(msg)
+                decode(bytes, Method::ReadGroupRequest)
             }
             ("/influxdata.platform.storage.Storage/ReadFilter", Response)
             | ("/influxdata.platform.storage.Storage/ReadGroup", Response)
             | ("/influxdata.platform.storage.Storage/ReadWindowAggregate", Response) => {
-                let msg = ReadResponse::decode(bytes).expect("Error decoding ReadResponse");
-                Self::ReadResponse(msg)
+                decode(bytes, Method::ReadResponse)
             }
 
-            _ => {
-                // fallback to unknown
-                println!(
-                    "Unknown how to decode {} from {} bytes",
-                    method_name,
-                    bytes.len()
-                );
-                Self::Unknown { method_name, bytes }
+            _ => Ok(Method::Unknown {
+                method_name: method_name.to_string(),
+                bytes: bytes.clone(),
+            }),
+        }
+    }
+}
+
+/// Decodes `bytes` as message `M`, wrapped with `variant`
+fn decode<M>(bytes: &Bytes, variant: impl FnOnce(M) -> Method) -> Result<Method, String>
+where
+    M: prost::Message + Default,
+{
+    M::decode(bytes.clone()).map(variant).map_err(|e| {
+        let name = std::any::type_name::<M>()
+            .rsplit("::")
+            .next()
+            .unwrap_or("message");
+        format!("Error decoding {}: {}", name, e)
+    })
+}
+
+/// Decodes the `ReadSource` (org, bucket and partition ids) carried in
+/// the `Any` source of storage requests
+pub fn decode_read_source(source: &Any) -> Option<ReadSource> {
+    use prost::Message;
+
+    if !source.type_url.ends_with(".ReadSource") {
+        return None;
+    }
+    ReadSource::decode(&source.value[..]).ok()
+}
+
+/// Formats an org or bucket id the way InfluxDB does (16 hex digits),
+/// e.g. as found in the `influx-org-id` header
+pub fn format_id(id: u64) -> String {
+    format!("{:016x}", id)
+}
+
+/// e.g. `org_id=0000000000000001 bucket_id=0000000000000002 partition_id=3`
+pub fn format_read_source(read_source: &ReadSource) -> String {
+    format!(
+        "org_id={} bucket_id={} partition_id={}",
+        format_id(read_source.org_id),
+        format_id(read_source.bucket_id),
+        read_source.partition_id
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use generated_types::influxdata::platform::storage::TimestampRange;
+    use prost::Message;
+
+    use super::*;
+
+    fn source(org_id: u64, bucket_id: u64) -> Any {
+        Any {
+            type_url: READ_SOURCE_TYPE_URL.to_string(),
+            value: ReadSource {
+                org_id,
+                bucket_id,
+                partition_id: 3,
             }
+            .encode_to_vec()
+            .into(),
         }
     }
+
+    #[test]
+    fn read_source() {
+        let request = Method::ReadFilterRequest(ReadFilterRequest {
+            read_source: Some(source(0xabcd, 0x1234)),
+            ..Default::default()
+        });
+        let read_source = request.read_source().unwrap();
+        assert_eq!(
+            format_read_source(&read_source),
+            "org_id=000000000000abcd bucket_id=0000000000001234 partition_id=3"
+        );
+
+        let request = Method::TagKeysRequest(TagKeysRequest {
+            tags_source: Some(source(1, 2)),
+            ..Default::default()
+        });
+        assert_eq!(request.read_source().unwrap().bucket_id, 2);
+    }
+
+    #[test]
+    fn missing_read_source() {
+        let request = Method::ReadFilterRequest(ReadFilterRequest::default());
+        assert_eq!(request.read_source(), None);
+
+        // some other message in the `Any`
+        let request = Method::ReadFilterRequest(ReadFilterRequest {
+            read_source: Some(Any {
+                type_url: "type.googleapis.com/google.protobuf.Empty".to_string(),
+                value: vec![].into(),
+            }),
+            ..Default::default()
+        });
+        assert_eq!(request.read_source(), None);
+
+        assert_eq!(Method::CapabilitiesRequest().read_source(), None);
+    }
+
+    /// Encodes `message` and decodes it again as `method_type` of `method`
+    fn round_trip<M>(method: &str, method_type: MethodType, message: M, variant: fn(M) -> Method)
+    where
+        M: Message + Clone,
+    {
+        let method_name = format!("/{}/{}", STORAGE_SERVICE_NAME, method);
+        let bytes = message.encode_to_vec().into();
+        let decoded = StorageDecoder.decode(&method_name, &bytes, method_type);
+        assert_eq!(decoded, Ok(variant(message)), "{}", method_name);
+    }
+
+    fn range() -> Option<TimestampRange> {
+        Some(TimestampRange {
+            start: 1_000,
+            end: 2_000,
+        })
+    }
+
+    #[test]
+    fn measurement_names() {
+        let request = MeasurementNamesRequest {
+            source: Some(source(1, 2)),
+            range: range(),
+            ..Default::default()
+        };
+        round_trip(
+            "MeasurementNames",
+            MethodType::Request,
+            request,
+            Method::MeasurementNamesRequest,
+        );
+
+        let response = StringValuesResponse {
+            values: vec![b"cpu".to_vec(), b"mem".to_vec()],
+        };
+        round_trip(
+            "MeasurementNames",
+            MethodType::Response,
+            response,
+            Method::StringValuesResponse,
+        );
+    }
+
+    #[test]
+    fn measurement_tag_keys() {
+        let request = MeasurementTagKeysRequest {
+            source: Some(source(1, 2)),
+            measurement: "cpu".to_string(),
+            range: range(),
+            ..Default::default()
+        };
+        round_trip(
+            "MeasurementTagKeys",
+            MethodType::Request,
+            request,
+            Method::MeasurementTagKeysRequest,
+        );
+
+        let response = StringValuesResponse {
+            values: vec![b"host".to_vec()],
+        };
+        round_trip(
+            "MeasurementTagKeys",
+            MethodType::Response,
+            response,
+            Method::StringValuesResponse,
+        );
+    }
+
+    #[test]
+    fn measurement_tag_values() {
+        let request = MeasurementTagValuesRequest {
+            source: Some(source(1, 2)),
+            measurement: "cpu".to_string(),
+            tag_key: "host".to_string(),
+            range: range(),
+            ..Default::default()
+        };
+        round_trip(
+            "MeasurementTagValues",
+            MethodType::Request,
+            request,
+            Method::MeasurementTagValuesRequest,
+        );
+
+        let response = StringValuesResponse {
+            values: vec![b"a".to_vec(), b"b".to_vec()],
+        };
+        round_trip(
+            "MeasurementTagValues",
+            MethodType::Response,
+            response,
+            Method::StringValuesResponse,
+        );
+    }
+
+    #[test]
+    fn measurement_fields() {
+        use generated_types::influxdata::platform::storage::measurement_fields_response::MessageField;
+
+        let request = MeasurementFieldsRequest {
+            source: Some(source(1, 2)),
+            measurement: "cpu".to_string(),
+            range: range(),
+            ..Default::default()
+        };
+        round_trip(
+            "MeasurementFields",
+            MethodType::Request,
+            request,
+            Method::MeasurementFieldsRequest,
+        );
+
+        let response = MeasurementFieldsResponse {
+            fields: vec![MessageField {
+                key: "usage".to_string(),
+                r#type: 1,
+                timestamp: 1_500,
+            }],
+        };
+        round_trip(
+            "MeasurementFields",
+            MethodType::Response,
+            response,
+            Method::MeasurementFieldsResponse,
+        );
+    }
+
+    #[test]
+    fn read_series_cardinality() {
+        let request = ReadSeriesCardinalityRequest {
+            read_series_cardinality_source: Some(source(1, 2)),
+            range: range(),
+            ..Default::default()
+        };
+        round_trip(
+            "ReadSeriesCardinality",
+            MethodType::Request,
+            request,
+            Method::ReadSeriesCardinalityRequest,
+        );
+
+        let response = Int64ValuesResponse { values: vec![42] };
+        round_trip(
+            "ReadSeriesCardinality",
+            MethodType::Response,
+            response,
+            Method::Int64ValuesResponse,
+        );
+    }
+
+    #[test]
+    fn tag_values_grouped_by_measurement_and_tag_key() {
+        use generated_types::influxdata::platform::storage::LiteralOrRegex;
+
+        let request = TagValuesGroupedByMeasurementAndTagKeyRequest {
+            source: Some(source(1, 2)),
+            measurement_patterns: vec![LiteralOrRegex::default()],
+            ..Default::default()
+        };
+        round_trip(
+            "TagValuesGroupedByMeasurementAndTagKey",
+            MethodType::Request,
+            request,
+            Method::TagValuesGroupedByMeasurementAndTagKeyRequest,
+        );
+
+        let response = TagValuesResponse {
+            measurement: "cpu".to_string(),
+            key: "host".to_string(),
+            values: vec!["a".to_string(), "b".to_string()],
+        };
+        round_trip(
+            "TagValuesGroupedByMeasurementAndTagKey",
+            MethodType::Response,
+            response,
+            Method::TagValuesResponse,
+        );
+    }
 }
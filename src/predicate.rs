@@ -0,0 +1,270 @@
+//! Renders storage `Predicate`s as readable infix expressions, e.g.
+//!
+//! ```text
+//! _measurement = "cpu" AND (host =~ /web.*/ OR region != "us")
+//! ```
+
+use generated_types::influxdata::platform::storage::{
+    node::{Comparison, Logical, Type, Value},
+    Node, Predicate,
+};
+
+use crate::view::format_tag_key;
+
+/// Renders `predicate` as an infix expression. Parentheses are only
+/// added where needed, so equivalent predicates render the same
+/// regardless of how their logical expressions were nested.
+pub fn format_predicate(predicate: &Predicate) -> String {
+    match &predicate.root {
+        Some(root) => format_node(root),
+        None => "<no predicate>".to_string(),
+    }
+}
+
+fn format_node(node: &Node) -> String {
+    match Type::from_i32(node.node_type) {
+        Some(Type::LogicalExpression) => format_logical(node),
+        Some(Type::ComparisonExpression) => format_comparison(node),
+        // parentheses are added based on precedence instead
+        Some(Type::ParenExpression) => match node.children.as_slice() {
+            [child] => format_node(child),
+            _ => format!("<invalid paren expression {:?}>", node),
+        },
+        Some(Type::TagRef) => match &node.value {
+            Some(Value::TagRefValue(key)) => format_tag_key(key),
+            _ => format!("<invalid tag ref {:?}>", node),
+        },
+        Some(Type::FieldRef) => match &node.value {
+            Some(Value::FieldRefValue(field)) => field.to_string(),
+            _ => format!("<invalid field ref {:?}>", node),
+        },
+        Some(Type::Literal) => match &node.value {
+            Some(value) => format_literal(value),
+            None => format!("<invalid literal {:?}>", node),
+        },
+        None => format!("<unknown node type {:?}>", node),
+    }
+}
+
+fn format_logical(node: &Node) -> String {
+    let logical = match &node.value {
+        Some(Value::Logical(logical)) => Logical::from_i32(*logical),
+        _ => None,
+    };
+    let (logical, operator) = match logical {
+        Some(Logical::And) => (Logical::And, " AND "),
+        Some(Logical::Or) => (Logical::Or, " OR "),
+        None => return format!("<invalid logical expression {:?}>", node),
+    };
+
+    let mut operands = vec![];
+    for child in &node.children {
+        collect_operands(child, logical, &mut operands);
+    }
+    operands.join(operator)
+}
+
+/// Flattens nested logical expressions with the same operator (e.g.
+/// `(a AND b) AND c` into `a AND b AND c`), and parenthesizes ones
+/// with a different operator
+fn collect_operands(node: &Node, logical: Logical, operands: &mut Vec<String>) {
+    let node = skip_parens(node);
+
+    let child_logical = match (Type::from_i32(node.node_type), &node.value) {
+        (Some(Type::LogicalExpression), Some(Value::Logical(child))) => Logical::from_i32(*child),
+        _ => None,
+    };
+
+    match child_logical {
+        Some(child) if child == logical => {
+            for child in &node.children {
+                collect_operands(child, logical, operands);
+            }
+        }
+        Some(_) => operands.push(format!("({})", format_node(node))),
+        None => operands.push(format_node(node)),
+    }
+}
+
+fn skip_parens(mut node: &Node) -> &Node {
+    while Type::from_i32(node.node_type) == Some(Type::ParenExpression) && node.children.len() == 1
+    {
+        node = &node.children[0];
+    }
+    node
+}
+
+fn format_comparison(node: &Node) -> String {
+    let comparison = match &node.value {
+        Some(Value::Comparison(comparison)) => Comparison::from_i32(*comparison),
+        _ => None,
+    };
+    let operator = match comparison {
+        Some(Comparison::Equal) => "=",
+        Some(Comparison::NotEqual) => "!=",
+        Some(Comparison::StartsWith) => "STARTS WITH",
+        Some(Comparison::Regex) => "=~",
+        Some(Comparison::NotRegex) => "!~",
+        Some(Comparison::Lt) => "<",
+        Some(Comparison::Lte) => "<=",
+        Some(Comparison::Gt) => ">",
+        Some(Comparison::Gte) => ">=",
+        None => return format!("<invalid comparison {:?}>", node),
+    };
+
+    match node.children.as_slice() {
+        [left, right] => format!("{} {} {}", format_node(left), operator, format_node(right)),
+        _ => format!("<invalid comparison {:?}>", node),
+    }
+}
+
+fn format_literal(value: &Value) -> String {
+    match value {
+        Value::StringValue(s) => format!("{:?}", s),
+        Value::BoolValue(b) => b.to_string(),
+        Value::IntValue(i) => i.to_string(),
+        Value::UintValue(u) => u.to_string(),
+        Value::FloatValue(f) => f.to_string(),
+        Value::RegexValue(regex) => format!("/{}/", regex),
+        Value::TagRefValue(key) => format_tag_key(key),
+        Value::FieldRefValue(field) => field.to_string(),
+        Value::Logical(_) | Value::Comparison(_) => format!("<invalid literal {:?}>", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(node_type: Type, value: Value, children: Vec<Node>) -> Node {
+        Node {
+            node_type: node_type as i32,
+            children,
+            value: Some(value),
+        }
+    }
+
+    fn comparison(comparison: Comparison, key: &[u8], literal: Value) -> Node {
+        node(
+            Type::ComparisonExpression,
+            Value::Comparison(comparison as i32),
+            vec![
+                node(Type::TagRef, Value::TagRefValue(key.to_vec()), vec![]),
+                node(Type::Literal, literal, vec![]),
+            ],
+        )
+    }
+
+    fn logical(logical: Logical, children: Vec<Node>) -> Node {
+        node(
+            Type::LogicalExpression,
+            Value::Logical(logical as i32),
+            children,
+        )
+    }
+
+    fn paren(child: Node) -> Node {
+        Node {
+            node_type: Type::ParenExpression as i32,
+            children: vec![child],
+            value: None,
+        }
+    }
+
+    fn format(root: Node) -> String {
+        format_predicate(&Predicate { root: Some(root) })
+    }
+
+    #[test]
+    fn comparisons_and_literals() {
+        let measurement = comparison(
+            Comparison::Equal,
+            b"\x00",
+            Value::StringValue("cpu".to_string()),
+        );
+        assert_eq!(format(measurement), "_measurement = \"cpu\"");
+
+        let host = comparison(
+            Comparison::Regex,
+            b"host",
+            Value::RegexValue("web.*".to_string()),
+        );
+        assert_eq!(format(host), "host =~ /web.*/");
+
+        let usage = comparison(Comparison::Gte, b"\xff", Value::FloatValue(0.5));
+        assert_eq!(format(usage), "_field >= 0.5");
+
+        assert_eq!(
+            format_predicate(&Predicate { root: None }),
+            "<no predicate>"
+        );
+    }
+
+    #[test]
+    fn parenthesizes_by_precedence() {
+        let cpu = || {
+            comparison(
+                Comparison::Equal,
+                b"\x00",
+                Value::StringValue("cpu".to_string()),
+            )
+        };
+        let web = || {
+            comparison(
+                Comparison::Regex,
+                b"host",
+                Value::RegexValue("web.*".to_string()),
+            )
+        };
+        let us = || {
+            comparison(
+                Comparison::NotEqual,
+                b"region",
+                Value::StringValue("us".to_string()),
+            )
+        };
+
+        let expected = "_measurement = \"cpu\" AND (host =~ /web.*/ OR region != \"us\")";
+        assert_eq!(
+            format(logical(
+                Logical::And,
+                vec![cpu(), paren(logical(Logical::Or, vec![web(), us()]))]
+            )),
+            expected
+        );
+        // the same, without the explicit parentheses
+        assert_eq!(
+            format(logical(
+                Logical::And,
+                vec![cpu(), logical(Logical::Or, vec![web(), us()])]
+            )),
+            expected
+        );
+
+        // nesting of the same operator is flattened
+        assert_eq!(
+            format(logical(
+                Logical::And,
+                vec![paren(logical(Logical::And, vec![cpu(), web()])), us()]
+            )),
+            "_measurement = \"cpu\" AND host =~ /web.*/ AND region != \"us\""
+        );
+    }
+
+    #[test]
+    fn invalid_nodes() {
+        let comparison = Node {
+            node_type: Type::ComparisonExpression as i32,
+            children: vec![],
+            value: Some(Value::Comparison(Comparison::Equal as i32)),
+        };
+        assert!(format(comparison).starts_with("<invalid comparison"));
+
+        let unknown = Node {
+            node_type: 1000,
+            children: vec![],
+            value: None,
+        };
+        assert!(format(unknown).starts_with("<unknown node type"));
+    }
+}
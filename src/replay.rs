@@ -0,0 +1,226 @@
+//! Replays captured gRPC calls against a live server
+
+use std::{collections::HashMap, str::FromStr};
+
+use bytes::Bytes;
+use chrono::Utc;
+use tonic::{
+    client::Grpc,
+    codegen::http::uri::PathAndQuery,
+    transport::{Channel, Endpoint},
+    Code, Request, Status,
+};
+
+use crate::{
+    call::{Call, CallState},
+    calls::Calls,
+    decoder::MethodRegistry,
+    entry::Logger,
+    error::Result,
+    grpc::{to_hashmap, to_metadata, RawCodec},
+};
+
+pub struct Replay {
+    target: String,
+    decoders: MethodRegistry,
+}
+
+impl Replay {
+    /// Replays calls against the gRPC server at `target`
+    /// (e.g. `http://localhost:8082`)
+    pub fn new(target: impl Into<String>) -> Self {
+        let target = target.into();
+
+        Self {
+            target,
+            decoders: MethodRegistry::default(),
+        }
+    }
+
+    /// Decode the replayed requests and responses with `decoders`
+    pub fn with_decoders(mut self, decoders: MethodRegistry) -> Self {
+        self.decoders = decoders;
+        self
+    }
+
+    /// Re-issues the request of each call, with its original method
+    /// and client headers, and records what the target answered as a
+    /// new set of [`Calls`]
+    pub async fn replay(&self, calls: &Calls) -> Result<Calls> {
+        let channel = Endpoint::from_shared(self.target.clone())
+            .map_err(|e| format!("Invalid target {}: {}", self.target, e))?
+            .connect()
+            .await
+            .map_err(|e| format!("Error connecting to {}: {}", self.target, e))?;
+
+        let mut replayed = Vec::with_capacity(calls.len());
+        for call in calls.iter() {
+            replayed.push(self.replay_call(channel.clone(), call).await);
+        }
+
+        println!("Replayed {} calls against {}", replayed.len(), self.target);
+        Ok(replayed.into())
+    }
+
+    async fn replay_call(&self, channel: Channel, call: &Call) -> Call {
+        let mut replayed = Call::new(call.id);
+        replayed
+            .with_logged_by(Logger::Client)
+            .with_peer(Some(self.target.clone()))
+            .with_client_headers(call.client_headers.clone());
+
+        if let Some(authority) = &call.authority {
+            replayed.with_authority(authority.clone());
+        }
+
+        // send the request as it was logged, rather than re-encoding the
+        // decoded message, which would lose unknown fields
+        let (method_name, request) = match (&call.method_name, &call.request_data) {
+            (Some(method_name), Some(request_data)) => (method_name, request_data.clone()),
+            _ => {
+                println!("Not replaying call without complete request: {}", call);
+                return replayed;
+            }
+        };
+
+        replayed
+            .with_method_name(method_name.clone())
+            .with_request_data(request.to_vec(), &self.decoders);
+        transition(&mut replayed, CallState::ClientHeaders);
+        transition(&mut replayed, CallState::Request);
+        transition(&mut replayed, CallState::HalfClosed);
+
+        let status = match send(
+            channel,
+            method_name,
+            request,
+            &call.client_headers,
+            &self.decoders,
+            &mut replayed,
+        )
+        .await
+        {
+            Ok(()) => Status::new(Code::Ok, ""),
+            Err(status) => status,
+        };
+
+        transition(&mut replayed, CallState::Trailer)
+            .with_status_code(status.code() as u32)
+            .with_status_message(status.message().to_string());
+
+        replayed
+    }
+}
+
+/// Sends `request` to `method_name` and records the server headers,
+/// responses and trailers in `call`
+async fn send(
+    channel: Channel,
+    method_name: &str,
+    request: Bytes,
+    headers: &HashMap<String, String>,
+    decoders: &MethodRegistry,
+    call: &mut Call,
+) -> std::result::Result<(), Status> {
+    let path = PathAndQuery::from_str(method_name).map_err(|e| {
+        Status::invalid_argument(format!("Invalid method name {}: {}", method_name, e))
+    })?;
+
+    let mut request = Request::new(request);
+    *request.metadata_mut() = to_metadata(headers);
+
+    let mut grpc = Grpc::new(channel);
+    grpc.ready()
+        .await
+        .map_err(|e| Status::unavailable(e.to_string()))?;
+
+    // Unary methods (e.g. Capabilities) look the same as server
+    // streaming ones with a single response on the wire
+    let response = grpc.server_streaming(request, path, RawCodec).await?;
+    transition(call, CallState::ServerHeaders)
+        .with_server_headers(to_hashmap(response.metadata().clone()));
+
+    let mut responses = response.into_inner();
+    while let Some(message) = responses.message().await? {
+        transition(call, CallState::Response).with_response_data(message.to_vec(), decoders);
+    }
+
+    if let Some(trailers) = responses.trailers().await? {
+        call.with_status_metadata(to_hashmap(trailers));
+    }
+
+    Ok(())
+}
+
+/// Notes that `call` reached `state` now, as logging it would have
+fn transition(call: &mut Call, state: CallState) -> &mut Call {
+    let now = Some(Utc::now());
+    call.with_timestamp(now).with_transition(state, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, time::Duration};
+
+    use super::*;
+    use crate::{call::Outcome, serve::Serve};
+
+    #[tokio::test]
+    async fn replayed_calls_are_logged_by_the_client() {
+        let decoders = MethodRegistry::default();
+        let mut recorded = Call::new(7);
+        recorded
+            .with_logged_by(Logger::Server)
+            .with_method_name("/influxdata.platform.storage.Storage/Capabilities".to_string())
+            .with_request_data(vec![], &decoders)
+            .with_response_data(vec![], &decoders)
+            .with_status_code(0);
+        let calls = Calls::from(vec![recorded]);
+
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        tokio::spawn(Serve::new(addr).serve(calls.clone()));
+
+        let replay = Replay::new(format!("http://{}", addr));
+        let mut replayed = None;
+        for _ in 0..50 {
+            match replay.replay(&calls).await {
+                Ok(calls) => {
+                    replayed = Some(calls);
+                    break;
+                }
+                // the server is not listening yet
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+        let replayed = replayed.expect("could not connect to the mock server");
+
+        let call = replayed.iter().next().unwrap();
+        assert_eq!(call.id, 7);
+        assert_eq!(call.logged_by, Logger::Client);
+        assert_eq!(call.status_code, Some(0));
+        assert_eq!(call.responses.len(), 1);
+        assert!(call.anomalies.is_empty(), "{:?}", call.anomalies);
+        assert_eq!(call.lifecycle.outcome(), Outcome::Completed);
+
+        let states: Vec<_> = call
+            .lifecycle
+            .transitions
+            .iter()
+            .map(|transition| transition.state)
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                CallState::ClientHeaders,
+                CallState::Request,
+                CallState::HalfClosed,
+                CallState::ServerHeaders,
+                CallState::Response,
+                CallState::Trailer,
+            ]
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! (De)serializes generated protobuf messages as their protobuf
+//! encoding, for use with `#[serde(with = "crate::serde_protobuf")]`.
+//!
+//! The serde impls of the generated types are meant for JSON: they skip
+//! fields with default values and can only be read back from maps, so
+//! non self-describing formats such as bincode can not read them back.
+
+use bytes::Bytes;
+use prost::Message;
+use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+pub fn serialize<M: Message, S: Serializer>(message: &M, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(&message.encode_to_vec())
+}
+
+pub fn deserialize<'de, M: Message + Default, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<M, D::Error> {
+    let bytes = Bytes::deserialize(deserializer)?;
+    M::decode(bytes).map_err(D::Error::custom)
+}
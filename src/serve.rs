@@ -0,0 +1,280 @@
+//! Serves recorded gRPC responses from a mock storage server
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use chrono::Utc;
+use futures::{
+    future::{ready, Ready},
+    stream::{iter, Iter},
+};
+use tonic::{
+    body::BoxBody,
+    codegen::{http, BoxFuture, Never, Service},
+    transport::{Body, NamedService, Server},
+    Code, Request, Response, Status,
+};
+
+use crate::{
+    call::Call,
+    calls::Calls,
+    decoder::MethodRegistry,
+    error::Result,
+    grpc::{to_hashmap, RawCodec},
+    matcher::{CallKey, Matcher},
+    methods::STORAGE_SERVICE_NAME,
+};
+
+type ResponseStream = Iter<std::vec::IntoIter<std::result::Result<Bytes, Status>>>;
+
+pub struct Serve {
+    addr: SocketAddr,
+    matcher: Matcher,
+    decoders: MethodRegistry,
+}
+
+impl Serve {
+    /// Serves recorded responses on `addr`
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            matcher: Matcher::new(),
+            decoders: MethodRegistry::default(),
+        }
+    }
+
+    /// Set the matcher used to find the recorded call for each request
+    pub fn with_matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = matcher;
+        self
+    }
+
+    /// Decode incoming requests with `decoders`, so they can be matched
+    /// against the recorded calls
+    pub fn with_decoders(mut self, decoders: MethodRegistry) -> Self {
+        self.decoders = decoders;
+        self
+    }
+
+    /// Answers each incoming request with the responses and status of
+    /// the recorded call with an equivalent request, until shut down
+    pub async fn serve(self, calls: Calls) -> Result<()> {
+        let storage = MockStorage::new(self.matcher, self.decoders, calls);
+        println!("Serving recorded calls on {}", self.addr);
+
+        Server::builder()
+            .add_service(storage)
+            .serve(self.addr)
+            .await
+            .map_err(|e| format!("Error serving on {}: {}", self.addr, e).into())
+    }
+}
+
+/// Recorded calls, grouped by the key of their request
+#[derive(Debug)]
+struct Recorded {
+    matcher: Matcher,
+    decoders: MethodRegistry,
+    calls: HashMap<CallKey, Vec<Call>>,
+    /// How many times each key was requested, so that repeated
+    /// requests are answered in the order they were captured
+    served: Mutex<HashMap<CallKey, usize>>,
+}
+
+impl Recorded {
+    /// Returns the recorded responses (and final status) of the call
+    /// equivalent to `call`
+    fn respond(&self, call: &Call) -> ResponseStream {
+        let key = self.matcher.key(call);
+        let recorded = match self.calls.get(&key) {
+            Some(recorded) => recorded,
+            None => {
+                let status = Status::not_found(format!("No recorded call matches {}", call));
+                return iter(vec![Err(status)]);
+            }
+        };
+
+        let mut served = self.served.lock().expect("mutex poisoned");
+        let count = served.entry(key).or_default();
+        let recorded_call = &recorded[(*count).min(recorded.len() - 1)];
+        *count += 1;
+
+        let mut responses = vec![];
+        // the responses as they were captured, rather than re-encoded
+        for (i, response) in recorded_call.response_data.iter().enumerate() {
+            match response {
+                Some(bytes) => responses.push(Ok(bytes.clone())),
+                None => {
+                    // rather than passing off what was captured as the whole response
+                    let status = Status::data_loss(format!(
+                        "Recorded response {} of {} was truncated in the capture",
+                        i + 1,
+                        recorded_call
+                    ));
+                    responses.push(Err(status));
+                    return iter(responses);
+                }
+            }
+        }
+
+        match recorded_call.status_code {
+            None | Some(0) => {}
+            Some(status_code) => responses.push(Err(Status::new(
+                Code::from(status_code as i32),
+                recorded_call.status_message.clone().unwrap_or_default(),
+            ))),
+        }
+
+        iter(responses)
+    }
+}
+
+/// A gRPC service that answers any `influxdata.platform.storage.Storage`
+/// method from the recorded calls
+#[derive(Debug, Clone)]
+struct MockStorage {
+    recorded: Arc<Recorded>,
+}
+
+impl MockStorage {
+    fn new(matcher: Matcher, decoders: MethodRegistry, calls: Calls) -> Self {
+        let mut by_key: HashMap<CallKey, Vec<Call>> = HashMap::new();
+        for call in calls.iter() {
+            by_key
+                .entry(matcher.key(call))
+                .or_default()
+                .push(call.clone());
+        }
+
+        let recorded = Recorded {
+            matcher,
+            decoders,
+            calls: by_key,
+            served: Mutex::new(HashMap::new()),
+        };
+
+        Self {
+            recorded: Arc::new(recorded),
+        }
+    }
+}
+
+impl NamedService for MockStorage {
+    const NAME: &'static str = STORAGE_SERVICE_NAME;
+}
+
+impl Service<http::Request<Body>> for MockStorage {
+    type Response = http::Response<BoxBody>;
+    type Error = Never;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<std::result::Result<(), Never>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+        let method = MockMethod {
+            recorded: Arc::clone(&self.recorded),
+            method_name: req.uri().path().to_string(),
+        };
+
+        Box::pin(async move {
+            let mut grpc = tonic::server::Grpc::new(RawCodec);
+            Ok(grpc.server_streaming(method, req).await)
+        })
+    }
+}
+
+/// Handles a request for a single gRPC method
+struct MockMethod {
+    recorded: Arc<Recorded>,
+    method_name: String,
+}
+
+impl Service<Request<Bytes>> for MockMethod {
+    type Response = Response<ResponseStream>;
+    type Error = Status;
+    type Future = Ready<std::result::Result<Self::Response, Status>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<std::result::Result<(), Status>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Bytes>) -> Self::Future {
+        let headers = to_hashmap(request.metadata().clone());
+
+        let mut call = Call::new(0);
+        call.with_timestamp(Some(Utc::now()))
+            .with_method_name(self.method_name.clone())
+            .with_client_headers(headers)
+            .with_request_data(request.into_inner().to_vec(), &self.recorded.decoders);
+
+        ready(Ok(Response::new(self.recorded.respond(&call))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METHOD_NAME: &str = "/influxdata.platform.storage.Storage/Capabilities";
+
+    fn request() -> Call {
+        let mut call = Call::new(1);
+        call.with_method_name(METHOD_NAME.to_string())
+            .with_request_data(vec![], &MethodRegistry::default());
+        call
+    }
+
+    fn respond(recorded: Call) -> Vec<std::result::Result<Bytes, Status>> {
+        let storage = MockStorage::new(
+            Matcher::new(),
+            MethodRegistry::default(),
+            vec![recorded].into(),
+        );
+        storage.recorded.respond(&request()).into_inner().collect()
+    }
+
+    #[test]
+    fn responses_are_served_as_captured() {
+        // an unknown field 15, which re-encoding the decoded response would drop
+        let captured = vec![0x78, 0x01];
+        let mut recorded = request();
+        recorded
+            .with_response_data(captured.clone(), &MethodRegistry::default())
+            .with_status_code(0);
+
+        let responses = respond(recorded);
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].as_ref().unwrap(), &captured);
+    }
+
+    #[test]
+    fn truncated_responses_are_not_served() {
+        let mut recorded = request();
+        recorded
+            .with_response_data(vec![], &MethodRegistry::default())
+            .with_truncated_response_data(10, vec![0x0a], &MethodRegistry::default())
+            .with_response_data(vec![], &MethodRegistry::default());
+
+        let responses = respond(recorded);
+        assert_eq!(responses.len(), 2);
+        assert!(responses[0].is_ok());
+        assert_eq!(responses[1].as_ref().unwrap_err().code(), Code::DataLoss);
+    }
+
+    #[test]
+    fn unrecorded_requests_are_not_found() {
+        let mut recorded = Call::new(1);
+        recorded.with_method_name(METHOD_NAME.replace("Capabilities", "Offsets"));
+
+        let responses = respond(recorded);
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].as_ref().unwrap_err().code(), Code::NotFound);
+    }
+}
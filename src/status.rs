@@ -0,0 +1,245 @@
+//! Decoding of the `google.rpc.Status` sent as `status_details` in the
+//! trailer of failed calls
+
+use std::fmt::Display;
+
+use bytes::Bytes;
+use generated_types::google::{
+    protobuf::Any,
+    rpc::{
+        BadRequest, DebugInfo, ErrorInfo, Help, LocalizedMessage, PreconditionFailure,
+        QuotaFailure, RequestInfo, ResourceInfo, RetryInfo, Status,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+/// The `status_details` of a call
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum StatusDetails {
+    /// A decoded `google.rpc.Status`
+    Status {
+        code: i32,
+        message: String,
+        details: Vec<StatusDetail>,
+        /// The details as they were sent
+        bytes: Bytes,
+    },
+
+    /// Details that are not a `google.rpc.Status`
+    Raw {
+        /// The details as they were sent
+        bytes: Bytes,
+        /// Why decoding failed
+        error: String,
+    },
+}
+
+impl StatusDetails {
+    /// Decodes `bytes` as a `google.rpc.Status` sent in a trailer with
+    /// `status_code`, keeping just the raw bytes if they are not one.
+    ///
+    /// As decoding skips unknown fields, most bytes (e.g. plain text
+    /// details) decode as *some* `Status`, so it is only taken to be
+    /// one if its code agrees with the trailer, or it has a code or
+    /// message at all.
+    pub fn decode(bytes: Vec<u8>, status_code: Option<u32>) -> Self {
+        use prost::Message;
+
+        let bytes: Bytes = bytes.into();
+        let status = match Status::decode(bytes.clone()) {
+            Ok(status) => status,
+            Err(e) => {
+                return Self::Raw {
+                    bytes,
+                    error: format!("Error decoding google.rpc.Status: {}", e),
+                }
+            }
+        };
+
+        let plausible = status_code == Some(status.code as u32)
+            || status.code != 0
+            || !status.message.is_empty();
+        if !plausible {
+            return Self::Raw {
+                bytes,
+                error: "Not a google.rpc.Status: no code or message".to_string(),
+            };
+        }
+
+        Self::Status {
+            code: status.code,
+            message: status.message,
+            details: status
+                .details
+                .into_iter()
+                .map(StatusDetail::decode)
+                .collect(),
+            bytes,
+        }
+    }
+}
+
+impl Display for StatusDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Status {
+                code,
+                message,
+                details,
+                ..
+            } => {
+                write!(f, "code {}: {:?}", code, message)?;
+                for detail in details {
+                    write!(f, " [{}]", detail)?;
+                }
+                Ok(())
+            }
+            Self::Raw { bytes, error } => write!(f, "{} raw bytes ({})", bytes.len(), error),
+        }
+    }
+}
+
+/// The standard error details of `google/rpc/error_details.proto`
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum StatusDetail {
+    ErrorInfo(#[serde(with = "crate::serde_protobuf")] ErrorInfo),
+    RetryInfo(#[serde(with = "crate::serde_protobuf")] RetryInfo),
+    DebugInfo(#[serde(with = "crate::serde_protobuf")] DebugInfo),
+    QuotaFailure(#[serde(with = "crate::serde_protobuf")] QuotaFailure),
+    PreconditionFailure(#[serde(with = "crate::serde_protobuf")] PreconditionFailure),
+    BadRequest(#[serde(with = "crate::serde_protobuf")] BadRequest),
+    RequestInfo(#[serde(with = "crate::serde_protobuf")] RequestInfo),
+    ResourceInfo(#[serde(with = "crate::serde_protobuf")] ResourceInfo),
+    Help(#[serde(with = "crate::serde_protobuf")] Help),
+    LocalizedMessage(#[serde(with = "crate::serde_protobuf")] LocalizedMessage),
+    /// Any other detail, or one that could not be decoded
+    Other(#[serde(with = "crate::serde_protobuf")] Any),
+}
+
+impl StatusDetail {
+    fn decode(any: Any) -> Self {
+        use prost::Message;
+
+        let value = any.value.clone();
+        let type_name = any.type_url.rsplit('/').next().unwrap_or_default();
+        let decoded = match type_name {
+            "google.rpc.ErrorInfo" => ErrorInfo::decode(value).map(Self::ErrorInfo),
+            "google.rpc.RetryInfo" => RetryInfo::decode(value).map(Self::RetryInfo),
+            "google.rpc.DebugInfo" => DebugInfo::decode(value).map(Self::DebugInfo),
+            "google.rpc.QuotaFailure" => QuotaFailure::decode(value).map(Self::QuotaFailure),
+            "google.rpc.PreconditionFailure" => {
+                PreconditionFailure::decode(value).map(Self::PreconditionFailure)
+            }
+            "google.rpc.BadRequest" => BadRequest::decode(value).map(Self::BadRequest),
+            "google.rpc.RequestInfo" => RequestInfo::decode(value).map(Self::RequestInfo),
+            "google.rpc.ResourceInfo" => ResourceInfo::decode(value).map(Self::ResourceInfo),
+            "google.rpc.Help" => Help::decode(value).map(Self::Help),
+            "google.rpc.LocalizedMessage" => {
+                LocalizedMessage::decode(value).map(Self::LocalizedMessage)
+            }
+            _ => return Self::Other(any),
+        };
+
+        decoded.unwrap_or(Self::Other(any))
+    }
+}
+
+impl Display for StatusDetail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ErrorInfo(detail) => write!(f, "{:?}", detail),
+            Self::RetryInfo(detail) => write!(f, "{:?}", detail),
+            Self::DebugInfo(detail) => write!(f, "{:?}", detail),
+            Self::QuotaFailure(detail) => write!(f, "{:?}", detail),
+            Self::PreconditionFailure(detail) => write!(f, "{:?}", detail),
+            Self::BadRequest(detail) => write!(f, "{:?}", detail),
+            Self::RequestInfo(detail) => write!(f, "{:?}", detail),
+            Self::ResourceInfo(detail) => write!(f, "{:?}", detail),
+            Self::Help(detail) => write!(f, "{:?}", detail),
+            Self::LocalizedMessage(detail) => write!(f, "{:?}", detail),
+            Self::Other(any) => write!(f, "{} ({} bytes)", any.type_url, any.value.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use super::*;
+
+    fn error_info() -> ErrorInfo {
+        ErrorInfo {
+            reason: "BUCKET_NOT_FOUND".to_string(),
+            domain: "storage".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decodes_status() {
+        let status = Status {
+            code: 5,
+            message: "bucket not found".to_string(),
+            details: vec![
+                Any {
+                    type_url: "type.googleapis.com/google.rpc.ErrorInfo".to_string(),
+                    value: error_info().encode_to_vec().into(),
+                },
+                Any {
+                    type_url: "type.googleapis.com/example.Detail".to_string(),
+                    value: vec![1, 2, 3].into(),
+                },
+            ],
+        };
+        let bytes = status.encode_to_vec();
+
+        match StatusDetails::decode(bytes.clone(), Some(5)) {
+            StatusDetails::Status {
+                code,
+                message,
+                details,
+                bytes: kept,
+            } => {
+                assert_eq!(code, 5);
+                assert_eq!(message, "bucket not found");
+                assert_eq!(details[0], StatusDetail::ErrorInfo(error_info()));
+                assert!(matches!(&details[1], StatusDetail::Other(any) if any.value.len() == 3));
+                assert_eq!(kept, bytes);
+            }
+            details => panic!("Unexpected {:?}", details),
+        }
+    }
+
+    #[test]
+    fn keeps_other_details_raw() {
+        // decodes as an empty Status, as unknown fields are skipped
+        let text = b"hi".to_vec();
+        assert!(Status::decode(text.as_slice()).is_ok());
+
+        match StatusDetails::decode(text.clone(), Some(13)) {
+            StatusDetails::Raw { bytes, .. } => assert_eq!(bytes, text),
+            details => panic!("Unexpected {:?}", details),
+        }
+
+        let garbage = vec![0xff, 0xff];
+        match StatusDetails::decode(garbage.clone(), Some(13)) {
+            StatusDetails::Raw { bytes, error } => {
+                assert_eq!(bytes, garbage);
+                assert!(error.starts_with("Error decoding google.rpc.Status"));
+            }
+            details => panic!("Unexpected {:?}", details),
+        }
+    }
+
+    #[test]
+    fn empty_status_matching_the_trailer() {
+        match StatusDetails::decode(vec![], Some(0)) {
+            StatusDetails::Status { code, details, .. } => {
+                assert_eq!(code, 0);
+                assert!(details.is_empty());
+            }
+            details => panic!("Unexpected {:?}", details),
+        }
+    }
+}
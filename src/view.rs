@@ -0,0 +1,415 @@
+//! Human oriented rendering of decoded methods: tag keys, tag values and
+//! string values are shown as (escaped) text rather than byte lists
+
+use std::fmt::{Display, Formatter, Result};
+
+use chrono::{TimeZone, Utc};
+use generated_types::{
+    google::protobuf::Any,
+    influxdata::platform::storage::{
+        read_response::{frame::Data, Frame},
+        Predicate, ReadResponse, Tag, TimestampRange,
+    },
+};
+
+use crate::{
+    methods::{decode_read_source, format_read_source, Method},
+    predicate::format_predicate,
+    wire::format_raw,
+};
+
+/// Tag key IOx uses for the measurement name
+pub const MEASUREMENT_TAG_KEY: &[u8] = b"\x00";
+
+/// Tag key IOx uses for the field name
+pub const FIELD_TAG_KEY: &[u8] = b"\xff";
+
+/// Displays a [`Method`] for humans. Falls back to `Debug` for methods
+/// without byte strings.
+#[derive(Debug, Clone, Copy)]
+pub struct MethodView<'a>(pub &'a Method);
+
+impl<'a> Display for MethodView<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self.0 {
+            Method::TagKeysRequest(r) => {
+                write!(f, "TagKeysRequest")?;
+                write_common(f, &r.tags_source, &r.range, &r.predicate)
+            }
+            Method::TagValuesRequest(r) => {
+                write!(
+                    f,
+                    "TagValuesRequest tag_key: {}",
+                    format_tag_key(&r.tag_key)
+                )?;
+                write_common(f, &r.tags_source, &r.range, &r.predicate)
+            }
+            Method::ReadFilterRequest(r) => {
+                write!(f, "ReadFilterRequest")?;
+                write_common(f, &r.read_source, &r.range, &r.predicate)
+            }
+            Method::ReadGroupRequest(r) => {
+                write!(
+                    f,
+                    "ReadGroupRequest group_keys: {:?} group: {} aggregate: {:?}",
+                    r.group_keys, r.group, r.aggregate
+                )?;
+                write_common(f, &r.read_source, &r.range, &r.predicate)
+            }
+            Method::ReadWindowAggregateRequest(r) => {
+                write!(
+                    f,
+                    "ReadWindowAggregateRequest window_every: {} offset: {} aggregate: {:?}",
+                    r.window_every, r.offset, r.aggregate
+                )?;
+                write_common(f, &r.read_source, &r.range, &r.predicate)
+            }
+            Method::MeasurementNamesRequest(r) => {
+                write!(f, "MeasurementNamesRequest")?;
+                write_common(f, &r.source, &r.range, &r.predicate)
+            }
+            Method::MeasurementTagKeysRequest(r) => {
+                write!(
+                    f,
+                    "MeasurementTagKeysRequest measurement: {:?}",
+                    r.measurement
+                )?;
+                write_common(f, &r.source, &r.range, &r.predicate)
+            }
+            Method::MeasurementTagValuesRequest(r) => {
+                write!(
+                    f,
+                    "MeasurementTagValuesRequest measurement: {:?} tag_key: {}",
+                    r.measurement,
+                    format_tag_key(r.tag_key.as_bytes())
+                )?;
+                write_common(f, &r.source, &r.range, &r.predicate)
+            }
+            Method::MeasurementFieldsRequest(r) => {
+                write!(
+                    f,
+                    "MeasurementFieldsRequest measurement: {:?}",
+                    r.measurement
+                )?;
+                write_common(f, &r.source, &r.range, &r.predicate)
+            }
+            Method::ReadSeriesCardinalityRequest(r) => {
+                write!(f, "ReadSeriesCardinalityRequest")?;
+                write_common(f, &r.read_series_cardinality_source, &r.range, &r.predicate)
+            }
+            Method::TagValuesGroupedByMeasurementAndTagKeyRequest(r) => {
+                let patterns: Vec<_> = r
+                    .measurement_patterns
+                    .iter()
+                    .map(|pattern| format!("{:?}", pattern.value))
+                    .collect();
+                write!(
+                    f,
+                    "TagValuesGroupedByMeasurementAndTagKeyRequest measurement_patterns: [{}] tag_key_predicate: {:?}",
+                    patterns.join(", "),
+                    r.tag_key_predicate
+                )?;
+                write_common(f, &r.source, &None, &r.condition)
+            }
+            Method::TagValuesResponse(r) => {
+                let values: Vec<_> = r.values.iter().map(|v| format!("{:?}", v)).collect();
+                write!(
+                    f,
+                    "TagValuesResponse measurement: {:?} key: {} [{}]",
+                    r.measurement,
+                    format_tag_key(r.key.as_bytes()),
+                    values.join(", ")
+                )
+            }
+            Method::StringValuesResponse(r) => {
+                write!(
+                    f,
+                    "StringValuesResponse [{}]",
+                    format_tag_values(r.values.iter())
+                )
+            }
+            Method::ReadResponse(r) => write_read_response(f, r),
+            Method::Truncated {
+                length,
+                bytes,
+                partial,
+            } => {
+                write!(f, "Truncated ({} of {} bytes)", bytes.len(), length)?;
+                match partial {
+                    Some(partial) => write!(f, " partial: {}", MethodView(partial)),
+                    None => write_indented(f, &format_raw(bytes)),
+                }
+            }
+            Method::DecodeError {
+                method_name,
+                method_type,
+                bytes,
+                error,
+            } => {
+                write!(
+                    f,
+                    "DecodeError {:?} for {} ({} bytes): {}",
+                    method_type,
+                    method_name,
+                    bytes.len(),
+                    error
+                )?;
+                write_indented(f, &format_raw(bytes))
+            }
+            Method::Dynamic { message, .. } => {
+                write!(f, "{}", message.type_name)?;
+                write_indented(f, &message.to_string())
+            }
+            Method::Unknown { method_name, bytes } => {
+                write!(f, "Unknown {} ({} bytes)", method_name, bytes.len())?;
+                write_indented(f, &format_raw(bytes))
+            }
+            method => write!(f, "{:?}", method),
+        }
+    }
+}
+
+/// Writes each line of `text` on a line of its own, indented
+fn write_indented(f: &mut Formatter<'_>, text: &str) -> Result {
+    for line in text.lines() {
+        write!(f, "\n    {}", line)?;
+    }
+    Ok(())
+}
+
+fn write_common(
+    f: &mut Formatter<'_>,
+    source: &Option<Any>,
+    range: &Option<TimestampRange>,
+    predicate: &Option<Predicate>,
+) -> Result {
+    match source.as_ref().and_then(decode_read_source) {
+        Some(read_source) => write!(f, " source: [{}]", format_read_source(&read_source))?,
+        None => write!(f, " source: {:?}", source)?,
+    }
+    if let Some(range) = range {
+        write!(
+            f,
+            " range: [{}, {})",
+            format_timestamp(range.start),
+            format_timestamp(range.end)
+        )?;
+    }
+    if let Some(predicate) = predicate {
+        write!(f, " predicate: {}", format_predicate(predicate))?;
+    }
+    Ok(())
+}
+
+/// One line per frame
+fn write_read_response(f: &mut Formatter<'_>, response: &ReadResponse) -> Result {
+    write!(f, "ReadResponse ({} frames)", response.frames.len())?;
+    for Frame { data } in &response.frames {
+        write!(f, "\n    ")?;
+        match data {
+            Some(Data::Group(group)) => write!(
+                f,
+                "group tag_keys: [{}] partition_key_vals: [{}]",
+                format_tag_keys(group.tag_keys.iter()),
+                format_tag_values(group.partition_key_vals.iter())
+            )?,
+            Some(Data::Series(series)) => write!(
+                f,
+                "series {} data_type: {}",
+                format_tags(&series.tags),
+                series.data_type
+            )?,
+            Some(Data::FloatPoints(points)) => {
+                write_points(f, "float", &points.timestamps, &points.values)?
+            }
+            Some(Data::IntegerPoints(points)) => {
+                write_points(f, "integer", &points.timestamps, &points.values)?
+            }
+            Some(Data::UnsignedPoints(points)) => {
+                write_points(f, "unsigned", &points.timestamps, &points.values)?
+            }
+            Some(Data::BooleanPoints(points)) => {
+                write_points(f, "boolean", &points.timestamps, &points.values)?
+            }
+            Some(Data::StringPoints(points)) => {
+                let values: Vec<_> = points.values.iter().map(|v| format!("{:?}", v)).collect();
+                write_points(f, "string", &points.timestamps, &values)?
+            }
+            None => write!(f, "<empty frame>")?,
+        }
+    }
+    Ok(())
+}
+
+fn write_points<T: Display>(
+    f: &mut Formatter<'_>,
+    name: &str,
+    timestamps: &[i64],
+    values: &[T],
+) -> Result {
+    let points: Vec<_> = timestamps
+        .iter()
+        .zip(values)
+        .map(|(ts, v)| format!("{}={}", ts, v))
+        .collect();
+    write!(f, "{} points: [{}]", name, points.join(", "))
+}
+
+/// `-inf` / `+inf` for the open ended "all time" bounds
+fn format_timestamp(ts: i64) -> String {
+    match ts {
+        i64::MIN => "-inf".to_string(),
+        i64::MAX => "+inf".to_string(),
+        ts => Utc.timestamp_nanos(ts).to_rfc3339(),
+    }
+}
+
+/// Renders a tag key, using `_measurement` and `_field` for the special
+/// keys IOx uses for them, and hex if the key is not UTF-8
+pub fn format_tag_key(key: &[u8]) -> String {
+    match key {
+        MEASUREMENT_TAG_KEY => "_measurement".to_string(),
+        FIELD_TAG_KEY => "_field".to_string(),
+        key => match std::str::from_utf8(key) {
+            Ok(key) => key.to_string(),
+            Err(_) => format_hex(key),
+        },
+    }
+}
+
+/// Renders a tag (or string) value as an escaped, quoted string, and hex
+/// if the value is not UTF-8
+pub fn format_tag_value(value: &[u8]) -> String {
+    match std::str::from_utf8(value) {
+        Ok(value) => format!("{:?}", value),
+        Err(_) => format_hex(value),
+    }
+}
+
+pub fn format_tag_keys<'a>(keys: impl Iterator<Item = &'a Vec<u8>>) -> String {
+    let keys: Vec<_> = keys.map(|key| format_tag_key(key)).collect();
+    keys.join(", ")
+}
+
+pub fn format_tag_values<'a>(values: impl Iterator<Item = &'a Vec<u8>>) -> String {
+    let values: Vec<_> = values.map(|value| format_tag_value(value)).collect();
+    values.join(", ")
+}
+
+/// e.g. `{_measurement="cpu", host="a"}`
+pub fn format_tags(tags: &[Tag]) -> String {
+    let tags: Vec<_> = tags
+        .iter()
+        .map(|tag| {
+            format!(
+                "{}={}",
+                format_tag_key(&tag.key),
+                format_tag_value(&tag.value)
+            )
+        })
+        .collect();
+
+    format!("{{{}}}", tags.join(", "))
+}
+
+fn format_hex(bytes: &[u8]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use generated_types::influxdata::platform::storage::{
+        read_response::{FloatPointsFrame, SeriesFrame},
+        ReadFilterRequest,
+    };
+
+    use super::*;
+
+    #[test]
+    fn tag_keys() {
+        assert_eq!(format_tag_key(MEASUREMENT_TAG_KEY), "_measurement");
+        assert_eq!(format_tag_key(FIELD_TAG_KEY), "_field");
+        assert_eq!(format_tag_key(b"host"), "host");
+        assert_eq!(format_tag_key(b"\xfe\x01"), "0xfe01");
+        // only the exact special keys are renamed
+        assert_eq!(format_tag_key(b"\x00\x00"), "\0\0");
+
+        let keys = [MEASUREMENT_TAG_KEY.to_vec(), b"host".to_vec()];
+        assert_eq!(format_tag_keys(keys.iter()), "_measurement, host");
+    }
+
+    #[test]
+    fn tag_values() {
+        assert_eq!(format_tag_value(b"a"), r#""a""#);
+        assert_eq!(format_tag_value(b"say \"hi\"\n"), r#""say \"hi\"\n""#);
+        assert_eq!(format_tag_value(b"\xff"), "0xff");
+
+        let values = [b"a".to_vec(), b"b".to_vec()];
+        assert_eq!(format_tag_values(values.iter()), r#""a", "b""#);
+    }
+
+    #[test]
+    fn tags() {
+        let tags = vec![
+            Tag {
+                key: MEASUREMENT_TAG_KEY.to_vec(),
+                value: b"cpu".to_vec(),
+            },
+            Tag {
+                key: b"host".to_vec(),
+                value: b"a".to_vec(),
+            },
+        ];
+        assert_eq!(format_tags(&tags), r#"{_measurement="cpu", host="a"}"#);
+        assert_eq!(format_tags(&[]), "{}");
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(i64::MIN), "-inf");
+        assert_eq!(format_timestamp(i64::MAX), "+inf");
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn methods() {
+        let request = Method::ReadFilterRequest(ReadFilterRequest {
+            range: Some(TimestampRange {
+                start: i64::MIN,
+                end: 0,
+            }),
+            ..Default::default()
+        });
+        assert_eq!(
+            MethodView(&request).to_string(),
+            "ReadFilterRequest source: None range: [-inf, 1970-01-01T00:00:00+00:00)"
+        );
+
+        let response = Method::ReadResponse(ReadResponse {
+            frames: vec![
+                Frame {
+                    data: Some(Data::Series(SeriesFrame {
+                        tags: vec![Tag {
+                            key: FIELD_TAG_KEY.to_vec(),
+                            value: b"usage".to_vec(),
+                        }],
+                        data_type: 0,
+                    })),
+                },
+                Frame {
+                    data: Some(Data::FloatPoints(FloatPointsFrame {
+                        timestamps: vec![1, 2],
+                        values: vec![0.5, 1.0],
+                    })),
+                },
+            ],
+        });
+        assert_eq!(
+            MethodView(&response).to_string(),
+            "ReadResponse (2 frames)\n    \
+             series {_field=\"usage\"} data_type: 0\n    \
+             float points: [1=0.5, 2=1]"
+        );
+    }
+}
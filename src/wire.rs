@@ -0,0 +1,335 @@
+//! Schemaless parsing of the protobuf wire format
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// A single field, as found on the wire
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub number: u32,
+    pub value: FieldValue,
+}
+
+/// The value of a field. Without a schema only the wire type is known,
+/// so e.g. a varint may be an integer, bool or enum, and a length
+/// delimited value may be a string, bytes, packed values or a message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FieldValue {
+    Varint(u64),
+    Fixed64(u64),
+    LengthDelimited(Bytes),
+    Fixed32(u32),
+}
+
+/// Iterates over the fields encoded in a message, stopping at the first
+/// field that is incomplete or malformed
+#[derive(Debug)]
+pub struct Fields<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Fields<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    /// Offset just past the last field that was read
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn read_varint(&self, offset: &mut usize) -> Option<u64> {
+        read_varint(self.bytes, offset)
+    }
+
+    fn read_fixed<const N: usize>(&self, offset: &mut usize) -> Option<[u8; N]> {
+        read_fixed(self.bytes, offset)
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Field;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut offset = self.offset;
+
+        let key = self.read_varint(&mut offset)?;
+        let number = u32::try_from(key >> 3).ok().filter(|n| *n != 0)?;
+        let value = match key & 0x7 {
+            0 => FieldValue::Varint(self.read_varint(&mut offset)?),
+            1 => FieldValue::Fixed64(u64::from_le_bytes(self.read_fixed(&mut offset)?)),
+            2 => {
+                let len = usize::try_from(self.read_varint(&mut offset)?).ok()?;
+                let end = offset.checked_add(len)?;
+                let value = self.bytes.get(offset..end)?;
+                offset = end;
+                FieldValue::LengthDelimited(Bytes::copy_from_slice(value))
+            }
+            5 => FieldValue::Fixed32(u32::from_le_bytes(self.read_fixed(&mut offset)?)),
+            // groups (deprecated) and invalid wire types
+            _ => return None,
+        };
+
+        self.offset = offset;
+        Some(Field { number, value })
+    }
+}
+
+/// Length of the longest prefix of `bytes` that consists of complete
+/// fields. Decoding just that prefix gives the leading fields of a
+/// message that was cut off.
+pub fn complete_prefix_len(bytes: &[u8]) -> usize {
+    let mut fields = Fields::new(bytes);
+    fields.by_ref().for_each(drop);
+    fields.offset()
+}
+
+/// Renders `bytes` as a tree of fields without a schema, similar to
+/// `protoc --decode_raw`. Length delimited values are shown as nested
+/// fields if they parse as a message (so, as with `protoc`, some short
+/// strings are too), as text if they are printable UTF-8, and as
+/// escaped bytes otherwise.
+pub fn format_raw(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    write_raw(&mut out, bytes, 0);
+    out
+}
+
+fn write_raw(out: &mut String, bytes: &[u8], indent: usize) {
+    use std::fmt::Write;
+
+    // as deep as `protoc` goes, so that corrupt (or crafted) payloads
+    // can not overflow the stack
+    const MAX_DEPTH: usize = 100;
+
+    let pad = "  ".repeat(indent);
+    let mut fields = Fields::new(bytes);
+    // writing to a String can not fail
+    for Field { number, value } in fields.by_ref() {
+        match value {
+            FieldValue::Varint(v) => writeln!(out, "{}{}: {}", pad, number, v).unwrap(),
+            FieldValue::Fixed64(v) => writeln!(out, "{}{}: 0x{:016x}", pad, number, v).unwrap(),
+            FieldValue::Fixed32(v) => writeln!(out, "{}{}: 0x{:08x}", pad, number, v).unwrap(),
+            FieldValue::LengthDelimited(v)
+                if indent < MAX_DEPTH && !v.is_empty() && complete_prefix_len(&v) == v.len() =>
+            {
+                writeln!(out, "{}{} {{", pad, number).unwrap();
+                write_raw(out, &v, indent + 1);
+                writeln!(out, "{}}}", pad).unwrap();
+            }
+            FieldValue::LengthDelimited(v) => match std::str::from_utf8(&v) {
+                Ok(text) if is_printable(text) => {
+                    writeln!(out, "{}{}: {:?}", pad, number, text).unwrap()
+                }
+                _ => writeln!(out, "{}{}: \"{}\"", pad, number, escape_bytes(&v)).unwrap(),
+            },
+        }
+    }
+
+    let rest = &bytes[fields.offset()..];
+    if !rest.is_empty() {
+        writeln!(
+            out,
+            "{}<{} undecodable bytes: \"{}\">",
+            pad,
+            rest.len(),
+            escape_bytes(rest)
+        )
+        .unwrap();
+    }
+}
+
+/// Text without control characters (other than line breaks and tabs)
+fn is_printable(text: &str) -> bool {
+    text.chars()
+        .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+}
+
+fn read_varint(bytes: &[u8], offset: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*offset)?;
+        *offset += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn read_fixed<const N: usize>(bytes: &[u8], offset: &mut usize) -> Option<[u8; N]> {
+    let fixed = bytes.get(*offset..offset.checked_add(N)?)?;
+    *offset += N;
+    fixed.try_into().ok()
+}
+
+/// The values of a packed repeated field of varints (e.g. `repeated
+/// int64`), or `None` if `bytes` are not valid packed varints
+pub fn unpack_varints(bytes: &[u8]) -> Option<Vec<u64>> {
+    let mut offset = 0;
+    let mut values = vec![];
+    while offset < bytes.len() {
+        values.push(read_varint(bytes, &mut offset)?);
+    }
+    Some(values)
+}
+
+/// The values of a packed repeated `fixed32`, `sfixed32` or `float` field
+pub fn unpack_fixed32(bytes: &[u8]) -> Option<Vec<u32>> {
+    let mut offset = 0;
+    let mut values = vec![];
+    while offset < bytes.len() {
+        values.push(u32::from_le_bytes(read_fixed(bytes, &mut offset)?));
+    }
+    Some(values)
+}
+
+/// The values of a packed repeated `fixed64`, `sfixed64` or `double` field
+pub fn unpack_fixed64(bytes: &[u8]) -> Option<Vec<u64>> {
+    let mut offset = 0;
+    let mut values = vec![];
+    while offset < bytes.len() {
+        values.push(u64::from_le_bytes(read_fixed(bytes, &mut offset)?));
+    }
+    Some(values)
+}
+
+/// Escapes `bytes` the way `protoc` prints strings and bytes: printable
+/// ASCII as is, and everything else as octal escapes
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            b'"' => escaped.push_str("\\\""),
+            b'\'' => escaped.push_str("\\'"),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(b as char),
+            _ => escaped.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// field `number` with a length delimited `value`
+    fn length_delimited(number: u8, value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![number << 3 | 2];
+        let mut len = value.len();
+        while len >= 0x80 {
+            bytes.push((len as u8) | 0x80);
+            len >>= 7;
+        }
+        bytes.push(len as u8);
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    #[test]
+    fn fields() {
+        let bytes = [
+            vec![0x08, 0x96, 0x01],
+            vec![0x11, 1, 0, 0, 0, 0, 0, 0, 0],
+            length_delimited(3, b"abc"),
+            vec![0x25, 2, 0, 0, 0],
+        ]
+        .concat();
+
+        let fields: Vec<_> = Fields::new(&bytes).collect();
+        assert_eq!(
+            fields,
+            vec![
+                Field {
+                    number: 1,
+                    value: FieldValue::Varint(150)
+                },
+                Field {
+                    number: 2,
+                    value: FieldValue::Fixed64(1)
+                },
+                Field {
+                    number: 3,
+                    value: FieldValue::LengthDelimited(Bytes::from_static(b"abc"))
+                },
+                Field {
+                    number: 4,
+                    value: FieldValue::Fixed32(2)
+                },
+            ]
+        );
+        assert_eq!(complete_prefix_len(&bytes), bytes.len());
+    }
+
+    #[test]
+    fn truncated_fields() {
+        let bytes = [vec![0x08, 0x96, 0x01], length_delimited(2, b"abc")].concat();
+
+        for len in 3..bytes.len() {
+            assert_eq!(complete_prefix_len(&bytes[..len]), 3, "{}", len);
+        }
+        assert_eq!(complete_prefix_len(&bytes[..2]), 0);
+        // field number 0 and groups are not valid
+        assert_eq!(complete_prefix_len(&[0x00, 0x01]), 0);
+        assert_eq!(complete_prefix_len(&[0x0b, 0x0c]), 0);
+    }
+
+    #[test]
+    fn format_nested_messages_strings_and_bytes() {
+        let bytes = [
+            length_delimited(1, b"mydb"),
+            length_delimited(2, &[0x08, 0x01]),
+            length_delimited(3, &[0xff, 0x00]),
+            vec![0x20, 0x01],
+        ]
+        .concat();
+
+        assert_eq!(
+            format_raw(&bytes),
+            "1: \"mydb\"\n2 {\n  1: 1\n}\n3: \"\\377\\000\"\n4: 1\n"
+        );
+    }
+
+    #[test]
+    fn format_undecodable_rest() {
+        let bytes = [vec![0x08, 0x01], vec![0x12, 0x05, b'a']].concat();
+
+        assert_eq!(
+            format_raw(&bytes),
+            "1: 1\n<3 undecodable bytes: \"\\022\\005a\">\n"
+        );
+    }
+
+    #[test]
+    fn format_caps_depth() {
+        let mut bytes = vec![0x08, 0x01];
+        for _ in 0..1000 {
+            bytes = length_delimited(1, &bytes);
+        }
+
+        let formatted = format_raw(&bytes);
+        let nested = formatted.lines().filter(|line| line.ends_with('{')).count();
+        assert_eq!(nested, 100);
+    }
+
+    #[test]
+    fn unpack() {
+        assert_eq!(unpack_varints(&[0x01, 0x96, 0x01]), Some(vec![1, 150]));
+        assert_eq!(unpack_varints(&[0x96]), None);
+        assert_eq!(unpack_fixed32(&[1, 0, 0, 0, 2, 0, 0, 0]), Some(vec![1, 2]));
+        assert_eq!(unpack_fixed32(&[1, 0, 0]), None);
+        assert_eq!(unpack_fixed64(&[1, 0, 0, 0, 0, 0, 0, 0]), Some(vec![1]));
+        assert_eq!(unpack_fixed64(&[]), Some(vec![]));
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(escape_bytes(b"a\"b\n\x00\xff"), "a\\\"b\\n\\000\\377");
+    }
+}